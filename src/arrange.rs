@@ -0,0 +1,85 @@
+use crate::types::{Head, HeadConfig};
+
+/// How `arrange_heads` lays out the enabled heads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrangeStrategy {
+    /// Side by side, top edges aligned
+    Horizontal,
+    /// Stacked on top of each other, left edges aligned
+    Vertical,
+    /// Rows of equal length, as close to square as possible
+    Grid,
+}
+
+impl std::str::FromStr for ArrangeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "horizontal" => Ok(ArrangeStrategy::Horizontal),
+            "vertical" => Ok(ArrangeStrategy::Vertical),
+            "grid" => Ok(ArrangeStrategy::Grid),
+            _ => Err(format!(
+                "invalid strategy {:?}, must be horizontal, vertical or grid",
+                s
+            )),
+        }
+    }
+}
+
+/// Mark the enabled heads that are where the compositor would place them by
+/// itself with `auto_position`: side by side from the left edge, with their
+/// top edges at 0. From the first head that is anywhere else on, heads keep
+/// their exact positions. Mirroring heads are not placed at all.
+pub fn detect_auto_positions(heads: &mut [Head]) {
+    let mut configs: Vec<&mut HeadConfig> = heads
+        .iter_mut()
+        .filter_map(|h| h.config.as_mut())
+        .filter(|c| c.mirror_of.is_none())
+        .collect();
+    configs.sort_by_key(|c| (c.x, c.y));
+
+    let mut right_edge = 0;
+    for config in configs {
+        if config.x != right_edge || config.y != 0 {
+            break;
+        }
+        right_edge += config.logical_size().0;
+        config.auto_position = true;
+    }
+}
+
+/// Reposition the enabled heads so that they do not overlap, keeping their
+/// modes, scales and transforms. Heads keep the order they currently have,
+/// from top to bottom and left to right.
+pub fn arrange_heads(heads: &mut [Head], strategy: ArrangeStrategy) {
+    let mut configs: Vec<&mut HeadConfig> =
+        heads.iter_mut().filter_map(|h| h.config.as_mut()).collect();
+    match strategy {
+        ArrangeStrategy::Horizontal => configs.sort_by_key(|c| (c.x, c.y)),
+        ArrangeStrategy::Vertical | ArrangeStrategy::Grid => configs.sort_by_key(|c| (c.y, c.x)),
+    }
+
+    let columns = match strategy {
+        ArrangeStrategy::Horizontal => configs.len().max(1),
+        ArrangeStrategy::Vertical => 1,
+        ArrangeStrategy::Grid => (configs.len() as f64).sqrt().ceil().max(1.0) as usize,
+    };
+    let sizes: Vec<(i32, i32)> = configs.iter().map(|c| c.logical_size()).collect();
+
+    // Each column is as wide as its widest head, and each row as high as its
+    // highest head
+    let mut column_widths = vec![0; columns];
+    let mut row_heights = vec![0; sizes.len().div_ceil(columns)];
+    for (idx, (width, height)) in sizes.iter().enumerate() {
+        let column_width = &mut column_widths[idx % columns];
+        *column_width = (*column_width).max(*width);
+        let row_height = &mut row_heights[idx / columns];
+        *row_height = (*row_height).max(*height);
+    }
+
+    for (idx, config) in configs.into_iter().enumerate() {
+        config.x = column_widths[..idx % columns].iter().sum();
+        config.y = row_heights[..idx / columns].iter().sum();
+    }
+}
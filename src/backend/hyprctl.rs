@@ -1,67 +1,438 @@
-use log::debug;
+use std::collections::HashMap;
+
 use serde::Deserialize;
+use tracing::{debug, info, instrument, warn};
 
-use crate::backend::Backend;
-use crate::types::{Head, HeadConfig};
+use crate::backend::{
+    Backend, Capabilities, apply_order, command_line, failed_error, parse_output, spawn_error,
+};
+use crate::config::{ModePolicy, ScalePolicy};
+use crate::types::{
+    AvailableMode, Compositor, Head, HeadConfig, HeadMode, Transform, WorkspaceAssignment,
+};
 
 pub struct HyprctlBackend {
     executable: String,
+    args: Vec<String>,
+    monitorv2: bool,
+    scale_policy: ScalePolicy,
+    mode_policy: ModePolicy,
+    instance: Option<String>,
+    dry_run: bool,
 }
 
 impl HyprctlBackend {
     pub fn new(executable: String) -> Self {
-        Self { executable }
+        Self {
+            executable,
+            args: Vec::new(),
+            monitorv2: false,
+            scale_policy: ScalePolicy::NearestValid,
+            mode_policy: ModePolicy::First,
+            instance: None,
+            dry_run: false,
+        }
     }
-}
 
-impl Backend for HyprctlBackend {
-    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
-        let output = std::process::Command::new(&self.executable)
+    /// Only log the commands that would change heads instead of running them
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Talk to the Hyprland instance with the given signature instead of the
+    /// one in `HYPRLAND_INSTANCE_SIGNATURE`
+    pub fn with_instance(mut self, instance: Option<String>) -> Self {
+        self.instance = instance;
+        self
+    }
+
+    /// Pick the current mode of heads for which hyprctl does not report one
+    /// with the given policy
+    pub fn with_mode_policy(mut self, mode_policy: ModePolicy) -> Self {
+        self.mode_policy = mode_policy;
+        self
+    }
+
+    /// Adjust scales with the given policy instead of `NearestValid`
+    pub fn with_scale_policy(mut self, scale_policy: Option<ScalePolicy>) -> Self {
+        if let Some(scale_policy) = scale_policy {
+            self.scale_policy = scale_policy;
+        }
+        self
+    }
+
+    /// Pass additional arguments to every invocation of hyprctl
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    fn run(&self, mut cmd: std::process::Command) -> anyhow::Result<()> {
+        if self.dry_run {
+            info!(command = %command_line(&cmd), "Would execute");
+            return Ok(());
+        }
+        debug!(command = %command_line(&cmd), "Executing");
+        let status = cmd
+            .status()
+            .map_err(|err| spawn_error(&self.executable, err))?;
+        if !status.success() {
+            return Err(failed_error(&self.executable));
+        }
+        Ok(())
+    }
+
+    fn command(&self) -> std::process::Command {
+        let mut cmd = std::process::Command::new(&self.executable);
+        if let Some(ref instance) = self.instance {
+            cmd.env("HYPRLAND_INSTANCE_SIGNATURE", instance);
+        }
+        cmd.args(&self.args);
+        cmd
+    }
+
+    /// Configure heads with `monitorv2` blocks, which need a recent version
+    /// of Hyprland, instead of the flat `monitor` syntax
+    pub fn with_monitorv2(mut self, monitorv2: bool) -> Self {
+        self.monitorv2 = monitorv2;
+        self
+    }
+
+    /// Keywords configuring an enabled head with the `monitor` syntax
+    fn monitor_keywords(&self, name: &str, head: &Head, cfg: &HeadConfig) -> Vec<String> {
+        let mut keyword = format!(
+            "keyword monitor {},{},{},{},transform,{},vrr,{}",
+            name,
+            cfg.custom_mode
+                .clone()
+                .unwrap_or_else(|| format!("{}x{}@{}", cfg.width, cfg.height, cfg.refresh_rate)),
+            position(cfg),
+            self.scale_policy.apply(cfg),
+            i32::from(cfg.transform),
+            if cfg.vrr { 1 } else { 0 },
+        );
+        if let Some(bitdepth) = cfg.bitdepth {
+            keyword += &format!(",bitdepth,{}", bitdepth);
+        }
+        if let Some(ref cm) = cfg.cm {
+            keyword += &format!(",cm,{}", cm);
+        }
+        if let Some(sdr_brightness) = cfg.sdr_brightness {
+            keyword += &format!(",sdrbrightness,{}", sdr_brightness);
+        }
+        if let Some(sdr_saturation) = cfg.sdr_saturation {
+            keyword += &format!(",sdrsaturation,{}", sdr_saturation);
+        }
+        if let Some(ref mirror_of) = cfg.mirror_of {
+            keyword += &format!(",mirror,{}", mirror_of);
+        }
+
+        let mut keywords = vec![keyword];
+        if let Some((top, bottom, left, right)) = head.reserved {
+            keywords.push(format!(
+                "keyword monitor {},addreserved,{},{},{},{}",
+                name, top, bottom, left, right
+            ));
+        }
+        keywords
+    }
+
+    /// Keyword configuring an enabled head with a `monitorv2` block, one
+    /// field per line
+    fn monitorv2_keyword(&self, name: &str, head: &Head, cfg: &HeadConfig) -> String {
+        let mut fields = vec![
+            ("output", name.to_string()),
+            (
+                "mode",
+                cfg.custom_mode.clone().unwrap_or_else(|| {
+                    format!("{}x{}@{}", cfg.width, cfg.height, cfg.refresh_rate)
+                }),
+            ),
+            ("position", position(cfg)),
+            ("scale", self.scale_policy.apply(cfg).to_string()),
+            ("transform", i32::from(cfg.transform).to_string()),
+            ("vrr", if cfg.vrr { "1" } else { "0" }.to_string()),
+        ];
+        if let Some(bitdepth) = cfg.bitdepth {
+            fields.push(("bitdepth", bitdepth.to_string()));
+        }
+        if let Some(ref cm) = cfg.cm {
+            fields.push(("cm", cm.clone()));
+        }
+        if let Some(sdr_brightness) = cfg.sdr_brightness {
+            fields.push(("sdrbrightness", sdr_brightness.to_string()));
+        }
+        if let Some(sdr_saturation) = cfg.sdr_saturation {
+            fields.push(("sdrsaturation", sdr_saturation.to_string()));
+        }
+        if let Some(ref mirror_of) = cfg.mirror_of {
+            fields.push(("mirror", mirror_of.clone()));
+        }
+        if let Some((top, bottom, left, right)) = head.reserved {
+            fields.push((
+                "addreserved",
+                format!("{}, {}, {}, {}", top, bottom, left, right),
+            ));
+        }
+
+        let fields: Vec<String> = fields
+            .into_iter()
+            .map(|(field, value)| format!("    {} = {}\n", field, value))
+            .collect();
+        format!("keyword monitorv2 {{\n{}}}", fields.concat())
+    }
+
+    /// Arguments to `hyprctl --batch` configuring the heads
+    fn batch_args(&self, heads: &[Head]) -> Vec<String> {
+        let mut args = Vec::new();
+        for head in apply_order(heads) {
+            let Some(ref name) = head.name else {
+                continue;
+            };
+
+            let keywords = match head.config {
+                Some(ref cfg) if self.monitorv2 => vec![self.monitorv2_keyword(name, head, cfg)],
+                Some(ref cfg) => self.monitor_keywords(name, head, cfg),
+                None => vec![format!("keyword monitor {},disable", name)],
+            };
+            for keyword in keywords {
+                args.push(format!("{};", keyword));
+            }
+
+            for extra_command in &head.extra_commands {
+                args.push(format!("{};", extra_command));
+            }
+        }
+
+        // Hyprland only allows or disallows tearing as a whole, for windows with
+        // the `immediate` rule, so it is allowed if any head allows it, and
+        // otherwise left as set in the Hyprland config
+        if heads
+            .iter()
+            .any(|h| h.config.as_ref().and_then(|c| c.tearing) == Some(true))
+        {
+            args.push("keyword general:allow_tearing true;".to_string());
+        }
+
+        args
+    }
+
+    /// Whether tearing is allowed, which Hyprland only has as a global setting
+    fn query_allow_tearing(&self) -> anyhow::Result<bool> {
+        let output = self
+            .command()
+            .arg("-j")
+            .arg("getoption")
+            .arg("general:allow_tearing")
+            .output()
+            .map_err(|err| spawn_error(&self.executable, err))?;
+        if !output.status.success() {
+            return Err(failed_error(&self.executable));
+        }
+        let option: HyprctlOption = parse_output(&self.executable, &output.stdout)?;
+        Ok(option.int != 0)
+    }
+
+    fn query_heads(&self) -> anyhow::Result<Vec<HyprctlHead>> {
+        let output = self
+            .command()
             .arg("-j")
             .arg("monitors")
             .arg("all")
-            .output()?;
+            .output()
+            .map_err(|err| spawn_error(&self.executable, err))?;
         if !output.status.success() {
-            return Err(anyhow::anyhow!("hyprctl failed"));
+            return Err(failed_error(&self.executable));
+        }
+        parse_output(&self.executable, &output.stdout)
+    }
+}
+
+/// Value of a Hyprland config option, as reported by `hyprctl getoption`
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct HyprctlOption {
+    int: i64,
+}
+
+/// Position of a head in the syntax of the `monitor` keywords
+fn position(cfg: &HeadConfig) -> String {
+    if cfg.auto_position {
+        "auto".to_string()
+    } else {
+        format!("{}x{}", cfg.x, cfg.y)
+    }
+}
+
+impl Backend for HyprctlBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            extra_commands: true,
+            reserved: true,
+            bitdepth: true,
+            cm: true,
+            sdr: true,
+            tearing: true,
+            global_tearing: true,
+            mirror: true,
+            create_if_missing: true,
+            primary: true,
+            workspaces: true,
+        }
+    }
+
+    #[instrument(skip_all, fields(backend = "hyprctl"))]
+    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+        let mut heads: Vec<Head> = self
+            .query_heads()?
+            .into_iter()
+            .map(|head| head.make_head(self.mode_policy))
+            .collect();
+        // Tearing is allowed for all heads or none, so it is only recorded
+        // for the heads if allowed
+        match self.query_allow_tearing() {
+            Ok(true) => {
+                for cfg in heads.iter_mut().filter_map(|h| h.config.as_mut()) {
+                    cfg.tearing = Some(true);
+                }
+            }
+            Ok(false) => (),
+            Err(err) => debug!(%err, "Failed to query general:allow_tearing"),
         }
-        let heads: Vec<HyprctlHead> = serde_json::from_slice(&output.stdout)?;
-        Ok(heads.into_iter().map(HyprctlHead::make_head).collect())
+        Ok(heads)
     }
 
+    #[instrument(skip_all, fields(backend = "hyprctl"))]
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+        Ok(self
+            .query_heads()?
+            .into_iter()
+            .map(|head| {
+                let modes = head.available_modes();
+                (head.name, modes)
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, fields(backend = "hyprctl", heads = heads.len()))]
     fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
-        let mut cmd = std::process::Command::new(&self.executable);
+        let mut cmd = self.command();
         cmd.stdout(std::process::Stdio::null());
         cmd.arg("--batch");
+        cmd.args(self.batch_args(heads));
+        self.run(cmd)
+    }
 
-        for head in heads {
-            let Some(ref name) = head.name else {
-                continue;
-            };
+    #[instrument(skip_all, fields(backend = "hyprctl"))]
+    fn focus_head(&self, head: &Head) -> anyhow::Result<()> {
+        let (Some(name), Some(cfg)) = (&head.name, &head.config) else {
+            return Ok(());
+        };
+        let mut cmd = self.command();
+        cmd.stdout(std::process::Stdio::null());
+        cmd.arg("--batch");
+        // Move the cursor there as well, which focusing does not do with
+        // `cursor:no_warps`
+        cmd.arg(format!("dispatch focusmonitor {};", name));
+        if !cfg.auto_position {
+            let (width, height) = HeadConfig {
+                scale: self.scale_policy.apply(cfg),
+                ..cfg.clone()
+            }
+            .logical_size();
+            cmd.arg(format!(
+                "dispatch movecursor {} {};",
+                cfg.x + width / 2,
+                cfg.y + height / 2
+            ));
+        }
+        self.run(cmd)
+    }
 
-            cmd.arg(if let Some(ref cfg) = head.config {
-                format!(
-                    "keyword monitor {},{}x{}@{},{}x{},{},transform,{},vrr,{};",
-                    name,
-                    cfg.width,
-                    cfg.height,
-                    cfg.refresh_rate,
-                    cfg.x,
-                    cfg.y,
-                    cfg.scale,
-                    cfg.transform,
-                    if cfg.vrr { 1 } else { 0 },
-                )
+    #[instrument(skip_all, fields(backend = "hyprctl"))]
+    fn compositor(&self) -> anyhow::Result<Option<Compositor>> {
+        let output = self
+            .command()
+            .arg("-j")
+            .arg("version")
+            .output()
+            .map_err(|err| spawn_error(&self.executable, err))?;
+        if !output.status.success() {
+            return Err(failed_error(&self.executable));
+        }
+
+        let version: HyprctlVersion = parse_output(&self.executable, &output.stdout)?;
+        Ok(Some(Compositor {
+            name: "Hyprland".to_string(),
+            // Older versions only report the tag
+            version: if version.version.is_empty() {
+                version.tag.trim_start_matches('v').to_string()
             } else {
-                format!("keyword monitor {},disable;", name)
-            });
+                version.version
+            },
+        }))
+    }
+
+    #[instrument(skip_all, fields(backend = "hyprctl"))]
+    fn get_workspaces(&self) -> anyhow::Result<Vec<WorkspaceAssignment>> {
+        let output = self
+            .command()
+            .arg("-j")
+            .arg("workspaces")
+            .output()
+            .map_err(|err| spawn_error(&self.executable, err))?;
+        if !output.status.success() {
+            return Err(failed_error(&self.executable));
         }
 
-        debug!("Executing {:?}", cmd);
-        if !cmd.status()?.success() {
-            return Err(anyhow::anyhow!("wlr-randr failed"));
+        Ok(
+            parse_output::<Vec<HyprctlWorkspace>>(&self.executable, &output.stdout)?
+                .into_iter()
+                .filter_map(HyprctlWorkspace::make_assignment)
+                .collect(),
+        )
+    }
+
+    #[instrument(skip_all, fields(backend = "hyprctl", workspaces = workspaces.len()))]
+    fn move_workspaces(&self, workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        let mut cmd = self.command();
+        cmd.stdout(std::process::Stdio::null());
+        cmd.arg("--batch");
+
+        for assignment in workspaces {
+            cmd.arg(format!(
+                "dispatch moveworkspacetomonitor {} {};",
+                assignment.workspace, assignment.head
+            ));
         }
 
-        Ok(())
+        self.run(cmd)
+    }
+
+    #[instrument(skip_all, fields(backend = "hyprctl", workspaces = workspaces.len()))]
+    fn bind_workspaces(&self, workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        let mut cmd = self.command();
+        cmd.stdout(std::process::Stdio::null());
+        cmd.arg("--batch");
+
+        for assignment in workspaces {
+            cmd.arg(format!(
+                "keyword workspace {},monitor:{};",
+                assignment.workspace, assignment.head
+            ));
+        }
+
+        self.run(cmd)
+    }
+
+    #[instrument(skip(self), fields(backend = "hyprctl"))]
+    fn create_head(&self, name: &str) -> anyhow::Result<()> {
+        let mut cmd = self.command();
+        cmd.stdout(std::process::Stdio::null());
+        cmd.args(["output", "create", "headless", name]);
+
+        self.run(cmd)
     }
 
     fn fallback_head_config(
@@ -69,7 +440,7 @@ impl Backend for HyprctlBackend {
         active_head_names: &[String],
         inactive_head_names: &[String],
     ) -> anyhow::Result<()> {
-        let mut cmd = std::process::Command::new(&self.executable);
+        let mut cmd = self.command();
         cmd.stdout(std::process::Stdio::null());
         cmd.arg("--batch");
 
@@ -81,16 +452,14 @@ impl Backend for HyprctlBackend {
             cmd.arg(format!("keyword monitor {},disable;", head));
         }
 
-        debug!("Executing {:?}", cmd);
-        if !cmd.status()?.success() {
-            return Err(anyhow::anyhow!("wlr-randr failed"));
-        }
-
-        Ok(())
+        self.run(cmd)
     }
 }
 
-#[derive(Debug, Deserialize)]
+// All fields default, so that output of Hyprland versions lacking some of them
+// can still be parsed
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
 struct HyprctlHead {
     name: String,
     make: String,
@@ -103,13 +472,70 @@ struct HyprctlHead {
     height: i32,
     #[serde(rename = "refreshRate")]
     refresh_rate: f64,
-    transform: i32,
+    transform: Transform,
+    #[serde(default = "default_scale")]
     scale: f64,
     vrr: bool,
+    focused: bool,
+    /// Name of the mirrored monitor, or `none`
+    #[serde(rename = "mirrorOf", default)]
+    mirror_of: String,
+    #[serde(rename = "availableModes")]
+    available_modes: Vec<String>,
+    #[serde(rename = "sdrBrightness")]
+    sdr_brightness: Option<f64>,
+    #[serde(rename = "sdrSaturation")]
+    sdr_saturation: Option<f64>,
+}
+
+fn default_scale() -> f64 {
+    1.0
 }
 
 impl HyprctlHead {
-    fn make_head(self) -> Head {
+    /// Parse the available modes, which hyprctl reports as e.g.
+    /// `1920x1080@60.00Hz`
+    ///
+    /// hyprctl does not report which mode is preferred.
+    fn available_modes(&self) -> Vec<AvailableMode> {
+        self.available_modes
+            .iter()
+            .filter_map(|mode| {
+                let (resolution, refresh_rate) = mode.strip_suffix("Hz")?.split_once('@')?;
+                let (width, height) = resolution.split_once('x')?;
+                let mode = HeadMode {
+                    width: width.parse().ok()?,
+                    height: height.parse().ok()?,
+                    refresh_rate: refresh_rate.parse().ok()?,
+                };
+                Some(AvailableMode {
+                    current: !self.disabled
+                        && mode.width == self.width
+                        && mode.height == self.height
+                        && (mode.refresh_rate - self.refresh_rate).abs() < 0.01,
+                    preferred: false,
+                    mode,
+                })
+            })
+            .collect()
+    }
+
+    fn make_head(mut self, mode_policy: ModePolicy) -> Head {
+        if !self.disabled
+            && (self.width == 0 || self.height == 0)
+            && let Some(AvailableMode { mode, .. }) = mode_policy.pick(&self.available_modes())
+        {
+            warn!(head = self.name, %mode, ?mode_policy, "No current mode reported, picked one");
+            self.width = mode.width;
+            self.height = mode.height;
+            self.refresh_rate = mode.refresh_rate;
+        }
+
+        let available_modes = self.available_modes();
+        let custom_mode = (!available_modes.is_empty()
+            && !available_modes.iter().any(|m| m.current))
+        .then(|| format!("{}x{}@{}", self.width, self.height, self.refresh_rate));
+
         Head {
             name: Some(self.name),
             make: self.make,
@@ -125,10 +551,195 @@ impl HyprctlHead {
                     scale: self.scale,
                     vrr: self.vrr,
                     transform: self.transform,
+                    custom_mode,
+                    bitdepth: None,
+                    cm: None,
+                    sdr_brightness: self.sdr_brightness,
+                    sdr_saturation: self.sdr_saturation,
+                    tearing: None,
+                    mirror_of: Some(self.mirror_of).filter(|m| !m.is_empty() && m != "none"),
+                    max_refresh: false,
+                    auto_position: false,
+                    mode_spec: None,
                 })
             } else {
                 None
             },
+            reserved: None,
+            extra_commands: Vec::new(),
+            create_if_missing: false,
+            primary: self.focused && !self.disabled,
+            mirror_group: None,
+            mirror_source: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct HyprctlVersion {
+    version: String,
+    tag: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct HyprctlWorkspace {
+    id: i64,
+    name: String,
+    monitor: String,
+}
+
+impl HyprctlWorkspace {
+    /// Special workspaces are not tied to a head, so are left out. Named
+    /// workspaces have negative ids and are referred to by name.
+    fn make_assignment(self) -> Option<WorkspaceAssignment> {
+        if self.name.starts_with("special") {
+            return None;
         }
+        Some(WorkspaceAssignment {
+            workspace: if self.id > 0 {
+                self.id.to_string()
+            } else {
+                format!("name:{}", self.name)
+            },
+            head: self.monitor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::tests::{head, head_config};
+
+    #[test]
+    fn parse_superset_of_fields() {
+        // Fields of newer Hyprland versions, and some made up, are ignored
+        let heads: Vec<HyprctlHead> = parse_output(
+            "hyprctl",
+            br#"[{
+                "id": 1,
+                "name": "DP-1",
+                "description": "Dell Inc. DELL U2720Q ABC123",
+                "make": "Dell Inc.",
+                "model": "DELL U2720Q",
+                "serial": "ABC123",
+                "width": 3840,
+                "height": 2160,
+                "physicalWidth": 600,
+                "physicalHeight": 340,
+                "refreshRate": 60.0,
+                "x": 1504,
+                "y": 0,
+                "activeWorkspace": {"id": 2, "name": "2"},
+                "specialWorkspace": {"id": 0, "name": ""},
+                "reserved": [0, 30, 0, 0],
+                "scale": 1.5,
+                "transform": 1,
+                "focused": true,
+                "dpmsStatus": true,
+                "vrr": true,
+                "solitary": "0",
+                "activelyTearing": false,
+                "directScanoutTo": "0",
+                "disabled": false,
+                "currentFormat": "XRGB2101010",
+                "mirrorOf": "none",
+                "availableModes": ["3840x2160@60.00Hz", "2560x1440@59.95Hz"],
+                "colorManagementPreset": "srgb",
+                "sdrBrightness": 1.2,
+                "sdrSaturation": 1.0,
+                "sdrMinLuminance": 0.2,
+                "sdrMaxLuminance": 80,
+                "futureField": {"nested": [1, 2, 3]}
+            }]"#,
+        )
+        .unwrap();
+        let head = heads
+            .into_iter()
+            .next()
+            .unwrap()
+            .make_head(ModePolicy::First);
+        assert_eq!(head.name.as_deref(), Some("DP-1"));
+        assert_eq!(head.serial, "ABC123");
+        assert!(head.primary);
+        let config = head.config.unwrap();
+        assert_eq!((config.width, config.height, config.x), (3840, 2160, 1504));
+        assert_eq!(config.transform, Transform::Rotate90);
+        assert_eq!(config.sdr_brightness, Some(1.2));
+        assert_eq!(config.custom_mode, None);
+    }
+
+    fn batch_args(monitorv2: bool) -> Vec<String> {
+        let heads = [
+            Head {
+                reserved: Some((0, 30, 0, 0)),
+                ..head(
+                    "DP-1",
+                    "1",
+                    Some(HeadConfig {
+                        x: 1504,
+                        bitdepth: Some(10),
+                        ..head_config(Transform::Rotate90, 1.5)
+                    }),
+                )
+            },
+            head("DP-2", "2", None),
+        ];
+        HyprctlBackend::new("hyprctl".to_string())
+            .with_monitorv2(monitorv2)
+            .with_scale_policy(Some(ScalePolicy::Exact))
+            .batch_args(&heads)
+    }
+
+    #[test]
+    fn batch_args_monitor() {
+        assert_eq!(
+            batch_args(false),
+            [
+                "keyword monitor DP-2,disable;",
+                "keyword monitor DP-1,2256x1504@60,1504x0,1.5,transform,1,vrr,0,bitdepth,10;",
+                "keyword monitor DP-1,addreserved,0,30,0,0;",
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_args_monitorv2() {
+        assert_eq!(
+            batch_args(true),
+            [
+                "keyword monitor DP-2,disable;",
+                "keyword monitorv2 {\n    output = DP-1\n    mode = 2256x1504@60\n    position = 1504x0\n    scale = 1.5\n    transform = 1\n    vrr = 0\n    bitdepth = 10\n    addreserved = 0, 30, 0, 0\n};",
+            ]
+        );
+    }
+
+    #[test]
+    fn tearing_is_only_ever_allowed() {
+        let tearing = |name, tearing| {
+            head(
+                name,
+                name,
+                Some(HeadConfig {
+                    tearing,
+                    ..head_config(Transform::Normal, 1.0)
+                }),
+            )
+        };
+        let backend = HyprctlBackend::new("hyprctl".to_string());
+        let allow_tearing = |heads: &[Head]| {
+            backend
+                .batch_args(heads)
+                .into_iter()
+                .filter(|arg| arg.contains("allow_tearing"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            allow_tearing(&[tearing("DP-1", Some(false)), tearing("DP-2", Some(true))]),
+            ["keyword general:allow_tearing true;"]
+        );
+        assert!(allow_tearing(&[tearing("DP-1", Some(false)), tearing("DP-2", None)]).is_empty());
     }
 }
@@ -1,16 +1,273 @@
-use crate::types::Head;
+use std::collections::HashMap;
 
-pub trait Backend {
+use crate::error::Error;
+use crate::types::{AvailableMode, Compositor, Head, WorkspaceAssignment};
+
+/// Which optional settings of saved heads a backend can apply
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    pub extra_commands: bool,
+    pub reserved: bool,
+    pub bitdepth: bool,
+    pub cm: bool,
+    /// SDR brightness and saturation
+    pub sdr: bool,
+    pub tearing: bool,
+    /// Whether tearing can only be allowed for all heads at once, so that a
+    /// head cannot have `tearing` other than `true` if another one has it
+    pub global_tearing: bool,
+    pub mirror: bool,
+    pub create_if_missing: bool,
+    pub primary: bool,
+    /// Whether workspaces can be bound to heads, which is not a setting of
+    /// saved heads, but of the config file
+    pub workspaces: bool,
+}
+
+impl Capabilities {
+    /// Names of the settings of `head` that are set, but cannot be applied,
+    /// given the other `heads` applied along with it
+    pub fn unsupported(&self, head: &Head, heads: &[Head]) -> Vec<&'static str> {
+        let config = head.config.as_ref();
+        let tearing = |h: &Head| h.config.as_ref().and_then(|c| c.tearing);
+        let tearing_overridden = self.global_tearing
+            && tearing(head) != Some(true)
+            && heads.iter().any(|h| tearing(h) == Some(true));
+        [
+            (
+                "extra_commands",
+                self.extra_commands,
+                !head.extra_commands.is_empty(),
+            ),
+            ("reserved", self.reserved, head.reserved.is_some()),
+            (
+                "bitdepth",
+                self.bitdepth,
+                config.is_some_and(|c| c.bitdepth.is_some()),
+            ),
+            ("cm", self.cm, config.is_some_and(|c| c.cm.is_some())),
+            (
+                "sdr_brightness",
+                self.sdr,
+                config.is_some_and(|c| c.sdr_brightness.is_some()),
+            ),
+            (
+                "sdr_saturation",
+                self.sdr,
+                config.is_some_and(|c| c.sdr_saturation.is_some()),
+            ),
+            (
+                "tearing",
+                self.tearing && !tearing_overridden,
+                config.is_some_and(|c| c.tearing.is_some()),
+            ),
+            (
+                "mirror_of",
+                self.mirror,
+                config.is_some_and(|c| c.mirror_of.is_some()),
+            ),
+            ("mirror_group", self.mirror, head.mirror_group.is_some()),
+            (
+                "create_if_missing",
+                self.create_if_missing,
+                head.create_if_missing,
+            ),
+            ("primary", self.primary, head.primary),
+        ]
+        .into_iter()
+        .filter(|(_, supported, set)| *set && !supported)
+        .map(|(field, _, _)| field)
+        .collect()
+    }
+}
+
+pub trait Backend: Send + Sync {
+    /// Which optional settings of saved heads can be applied
+    fn capabilities(&self) -> Capabilities;
     fn get_all_heads(&self) -> anyhow::Result<Vec<Head>>;
+    /// Modes supported by each head, keyed by head name
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>>;
     fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()>;
     fn fallback_head_config(
         &self,
         active_head_names: &[String],
         inactive_head_names: &[String],
     ) -> anyhow::Result<()>;
+    /// Name and version of the compositor, if the backend can tell
+    fn compositor(&self) -> anyhow::Result<Option<Compositor>> {
+        Ok(None)
+    }
+    /// Which head each workspace is on
+    fn get_workspaces(&self) -> anyhow::Result<Vec<WorkspaceAssignment>> {
+        Err(anyhow::anyhow!("backend does not support workspaces"))
+    }
+    /// Move workspaces to the given heads
+    fn move_workspaces(&self, _workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("backend does not support workspaces"))
+    }
+    /// Make the given heads show the workspaces from now on
+    fn bind_workspaces(&self, _workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("backend does not support workspaces"))
+    }
+    /// Create a headless output with the given name
+    fn create_head(&self, name: &str) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "backend cannot create head {}: not supported",
+            name
+        ))
+    }
+    /// Focus the given (enabled) head and move the cursor to it, after a
+    /// saved config marking it as primary has been applied. Backends
+    /// without a concept of focus ignore this.
+    fn focus_head(&self, _head: &Head) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// The heads in the order they should be configured in: first those to be
+/// disabled, then those to be enabled from left to right, so that enabled
+/// heads never overlap heads that are about to be disabled
+fn apply_order(heads: &[Head]) -> Vec<&Head> {
+    let mut ordered: Vec<&Head> = heads.iter().collect();
+    ordered.sort_by_key(|h| h.config.as_ref().map(|c| (c.x, c.y)));
+    ordered
+}
+
+/// The command line of `cmd` as a single string, for logging
+fn command_line(cmd: &std::process::Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '\'') {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            } else {
+                arg.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse the JSON output of a backend command. On failure, the part of the
+/// output the error occurred in is included in the error message.
+fn parse_output<T: serde::de::DeserializeOwned>(
+    executable: &str,
+    output: &[u8],
+) -> anyhow::Result<T> {
+    serde_json::from_slice(output).map_err(|err| {
+        let output = String::from_utf8_lossy(output);
+        let line = output
+            .lines()
+            .nth(err.line().saturating_sub(1))
+            .unwrap_or_default();
+        let start = err.column().saturating_sub(40);
+        let snippet: String = line.chars().skip(start).take(80).collect();
+        anyhow::Error::new(err).context(format!(
+            "failed to parse output of '{}' near {:?}",
+            executable, snippet
+        ))
+    })
+}
+
+/// Error for a backend command that failed
+fn failed_error(executable: &str) -> anyhow::Error {
+    Error::BackendFailed {
+        executable: executable.to_string(),
+    }
+    .into()
+}
+
+/// Error for a backend command that could not be run at all
+fn spawn_error(executable: &str, err: std::io::Error) -> anyhow::Error {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        Error::ExecutableNotFound {
+            executable: executable.to_string(),
+        }
+        .into()
+    } else {
+        anyhow::Error::new(err).context(format!("failed to run '{}'", executable))
+    }
 }
 
 mod hyprctl;
 pub use hyprctl::HyprctlBackend;
 mod wlr_randr;
 pub use wlr_randr::WlrRandrBackend;
+mod pretend;
+pub use pretend::PretendBackend;
+mod read_only;
+pub use read_only::ReadOnlyBackend;
+mod stable;
+pub use stable::StableBackend;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::tests::{head, head_config};
+    use crate::types::{HeadConfig, Transform};
+
+    #[test]
+    fn parse_output_error_shows_snippet() {
+        let err = parse_output::<Vec<Head>>("hyprctl", b"[{\"name\": \"DP-1\", oops}]")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("hyprctl"), "{}", err);
+        assert!(err.contains("oops"), "{}", err);
+    }
+
+    #[test]
+    fn apply_order_disables_first() {
+        let at = |x, y| {
+            Some(HeadConfig {
+                x,
+                y,
+                ..head_config(Transform::Normal, 1.0)
+            })
+        };
+        let heads = [
+            head("DP-1", "1", at(1504, 0)),
+            head("DP-2", "2", None),
+            head("DP-3", "3", at(0, 0)),
+            head("DP-4", "4", None),
+            head("DP-5", "5", at(0, 1003)),
+        ];
+        let names: Vec<&str> = apply_order(&heads)
+            .into_iter()
+            .map(|h| h.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["DP-2", "DP-4", "DP-3", "DP-5", "DP-1"]);
+    }
+
+    #[test]
+    fn unsupported_tearing_when_heads_disagree() {
+        let tearing = |name, tearing| {
+            head(
+                name,
+                name,
+                Some(HeadConfig {
+                    tearing,
+                    ..head_config(Transform::Normal, 1.0)
+                }),
+            )
+        };
+        let capabilities = Capabilities {
+            tearing: true,
+            global_tearing: true,
+            ..Default::default()
+        };
+        let heads = [tearing("DP-1", Some(true)), tearing("DP-2", Some(false))];
+        assert!(capabilities.unsupported(&heads[0], &heads).is_empty());
+        assert_eq!(capabilities.unsupported(&heads[1], &heads), ["tearing"]);
+
+        let heads = [tearing("DP-1", Some(false)), tearing("DP-2", None)];
+        assert!(capabilities.unsupported(&heads[0], &heads).is_empty());
+
+        let per_head = Capabilities {
+            global_tearing: false,
+            ..capabilities
+        };
+        let heads = [tearing("DP-1", Some(true)), tearing("DP-2", Some(false))];
+        assert!(per_head.unsupported(&heads[1], &heads).is_empty());
+    }
+}
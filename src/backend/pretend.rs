@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::backend::{Backend, Capabilities};
+use crate::types::{AvailableMode, Compositor, Head, WorkspaceAssignment};
+
+/// Wraps another backend, reporting a fixed set of heads instead of the
+/// connected ones. Everything else, including applying configs, is passed
+/// through, so this is meant for testing with a mock backend executable.
+pub struct PretendBackend {
+    inner: Box<dyn Backend>,
+    heads: Vec<Head>,
+}
+
+/// A head as listed in a file of pretend heads. Unlike in saved configs, the
+/// connector name is included.
+#[derive(Deserialize)]
+struct PretendHead {
+    name: String,
+    #[serde(flatten)]
+    head: Head,
+}
+
+impl PretendBackend {
+    pub fn new(inner: Box<dyn Backend>, heads: Vec<Head>) -> Self {
+        Self { inner, heads }
+    }
+
+    /// Pretend the heads listed in the JSON file at `path` are connected
+    pub fn from_file(inner: Box<dyn Backend>, path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read(path).map_err(|err| {
+            anyhow::Error::new(err).context(format!("failed to read {}", path.display()))
+        })?;
+        let heads: Vec<PretendHead> = serde_json::from_slice(&contents).map_err(|err| {
+            anyhow::Error::new(err).context(format!("invalid heads in {}", path.display()))
+        })?;
+        Ok(Self::new(
+            inner,
+            heads
+                .into_iter()
+                .map(|PretendHead { name, mut head }| {
+                    head.name = Some(name);
+                    head
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl Backend for PretendBackend {
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+        Ok(self.heads.clone())
+    }
+
+    /// Modes reported by the wrapped backend, plus the current mode of each
+    /// pretend head it does not know about
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+        let mut available_modes = self.inner.get_available_modes()?;
+        for head in &self.heads {
+            if let Some(ref name) = head.name
+                && let Some(ref config) = head.config
+            {
+                available_modes.entry(name.clone()).or_insert_with(|| {
+                    vec![AvailableMode {
+                        mode: config.mode(),
+                        current: true,
+                        preferred: true,
+                    }]
+                });
+            }
+        }
+        Ok(available_modes)
+    }
+
+    fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
+        self.inner.set_head_config(heads)
+    }
+
+    fn fallback_head_config(
+        &self,
+        active_head_names: &[String],
+        inactive_head_names: &[String],
+    ) -> anyhow::Result<()> {
+        self.inner
+            .fallback_head_config(active_head_names, inactive_head_names)
+    }
+
+    fn compositor(&self) -> anyhow::Result<Option<Compositor>> {
+        self.inner.compositor()
+    }
+
+    fn get_workspaces(&self) -> anyhow::Result<Vec<WorkspaceAssignment>> {
+        self.inner.get_workspaces()
+    }
+
+    fn move_workspaces(&self, workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        self.inner.move_workspaces(workspaces)
+    }
+
+    fn bind_workspaces(&self, workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        self.inner.bind_workspaces(workspaces)
+    }
+
+    fn create_head(&self, name: &str) -> anyhow::Result<()> {
+        self.inner.create_head(name)
+    }
+
+    fn focus_head(&self, head: &Head) -> anyhow::Result<()> {
+        self.inner.focus_head(head)
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::backend::{Backend, Capabilities};
+use crate::error::Error;
+use crate::types::{AvailableMode, Compositor, Head, WorkspaceAssignment};
+
+/// Wraps another backend, passing through queries, but refusing to change
+/// anything
+pub struct ReadOnlyBackend {
+    inner: Box<dyn Backend>,
+}
+
+impl ReadOnlyBackend {
+    pub fn new(inner: Box<dyn Backend>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Backend for ReadOnlyBackend {
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+        self.inner.get_all_heads()
+    }
+
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+        self.inner.get_available_modes()
+    }
+
+    fn set_head_config(&self, _heads: &[Head]) -> anyhow::Result<()> {
+        Err(Error::ReadOnly.into())
+    }
+
+    fn fallback_head_config(
+        &self,
+        _active_head_names: &[String],
+        _inactive_head_names: &[String],
+    ) -> anyhow::Result<()> {
+        Err(Error::ReadOnly.into())
+    }
+
+    fn compositor(&self) -> anyhow::Result<Option<Compositor>> {
+        self.inner.compositor()
+    }
+
+    fn get_workspaces(&self) -> anyhow::Result<Vec<WorkspaceAssignment>> {
+        self.inner.get_workspaces()
+    }
+
+    fn move_workspaces(&self, _workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        Err(Error::ReadOnly.into())
+    }
+
+    fn bind_workspaces(&self, _workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        Err(Error::ReadOnly.into())
+    }
+
+    fn create_head(&self, _name: &str) -> anyhow::Result<()> {
+        Err(Error::ReadOnly.into())
+    }
+
+    fn focus_head(&self, _head: &Head) -> anyhow::Result<()> {
+        Err(Error::ReadOnly.into())
+    }
+}
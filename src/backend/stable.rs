@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::backend::{Backend, Capabilities};
+use crate::types::{AvailableMode, Compositor, Head, WorkspaceAssignment};
+
+/// Wraps another backend, querying heads repeatedly until two consecutive
+/// queries agree, so that a state that is only momentarily inconsistent, e.g.
+/// right after a head has been connected, is not acted upon
+pub struct StableBackend {
+    inner: Box<dyn Backend>,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl StableBackend {
+    /// Query heads every `interval` until two queries agree, giving up after
+    /// `timeout`, in which case the last result is used
+    pub fn new(inner: Box<dyn Backend>, interval: Duration, timeout: Duration) -> Self {
+        Self {
+            inner,
+            interval,
+            timeout,
+        }
+    }
+}
+
+impl Backend for StableBackend {
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+        let start = Instant::now();
+        let mut heads = self.inner.get_all_heads()?;
+        loop {
+            if start.elapsed() >= self.timeout {
+                warn!(
+                    timeout = ?self.timeout,
+                    "Heads did not stabilize, using last query"
+                );
+                return Ok(heads);
+            }
+            std::thread::sleep(self.interval);
+
+            let next = self.inner.get_all_heads()?;
+            if next == heads {
+                return Ok(heads);
+            }
+            debug!("Heads changed between queries, querying again");
+            heads = next;
+        }
+    }
+
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+        self.inner.get_available_modes()
+    }
+
+    fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
+        self.inner.set_head_config(heads)
+    }
+
+    fn fallback_head_config(
+        &self,
+        active_head_names: &[String],
+        inactive_head_names: &[String],
+    ) -> anyhow::Result<()> {
+        self.inner
+            .fallback_head_config(active_head_names, inactive_head_names)
+    }
+
+    fn compositor(&self) -> anyhow::Result<Option<Compositor>> {
+        self.inner.compositor()
+    }
+
+    fn get_workspaces(&self) -> anyhow::Result<Vec<WorkspaceAssignment>> {
+        self.inner.get_workspaces()
+    }
+
+    fn move_workspaces(&self, workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        self.inner.move_workspaces(workspaces)
+    }
+
+    fn bind_workspaces(&self, workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        self.inner.bind_workspaces(workspaces)
+    }
+
+    fn create_head(&self, name: &str) -> anyhow::Result<()> {
+        self.inner.create_head(name)
+    }
+
+    fn focus_head(&self, head: &Head) -> anyhow::Result<()> {
+        self.inner.focus_head(head)
+    }
+}
@@ -1,80 +1,195 @@
-use crate::backend::Backend;
-use crate::types::{Head, HeadConfig};
-use log::debug;
+use std::collections::HashMap;
+
+use crate::backend::{
+    Backend, Capabilities, apply_order, command_line, failed_error, parse_output, spawn_error,
+};
+use crate::config::{ModePolicy, ScalePolicy};
+use crate::types::{AvailableMode, Head, HeadConfig, HeadMode};
 use serde::Deserialize;
+use tracing::{debug, info, instrument, warn};
 
 pub struct WlrRandrBackend {
     executable: String,
+    args: Vec<String>,
+    scale_policy: ScalePolicy,
+    mode_policy: ModePolicy,
+    isolate: bool,
+    dry_run: bool,
 }
 
 impl WlrRandrBackend {
     pub fn new(executable: String) -> Self {
-        Self { executable }
+        Self {
+            executable,
+            args: Vec::new(),
+            scale_policy: ScalePolicy::Exact,
+            mode_policy: ModePolicy::First,
+            isolate: false,
+            dry_run: false,
+        }
     }
-}
 
-impl Backend for WlrRandrBackend {
-    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
-        let output = std::process::Command::new(&self.executable)
-            .arg("--json")
-            .output()?;
-        if !output.status.success() {
-            return Err(anyhow::anyhow!("wlr-randr failed"));
+    /// Only log the commands that would change heads instead of running them
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Configure each head with a separate invocation of wlr-randr
+    pub fn with_isolate(mut self, isolate: bool) -> Self {
+        self.isolate = isolate;
+        self
+    }
+
+    /// Pick the current mode of heads for which wlr-randr does not flag one
+    /// with the given policy
+    pub fn with_mode_policy(mut self, mode_policy: ModePolicy) -> Self {
+        self.mode_policy = mode_policy;
+        self
+    }
+
+    /// Adjust scales with the given policy instead of `Exact`
+    pub fn with_scale_policy(mut self, scale_policy: Option<ScalePolicy>) -> Self {
+        if let Some(scale_policy) = scale_policy {
+            self.scale_policy = scale_policy;
         }
-        let heads: Vec<WlrRandrHead> = serde_json::from_slice(&output.stdout)?;
-        Ok(heads.into_iter().map(WlrRandrHead::make_head).collect())
+        self
     }
 
-    fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
+    /// Pass additional arguments to every invocation of wlr-randr
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    fn command(&self) -> std::process::Command {
         let mut cmd = std::process::Command::new(&self.executable);
+        cmd.args(&self.args);
+        cmd
+    }
+
+    /// Add the arguments configuring `head`, if it has a name
+    fn head_args(&self, cmd: &mut std::process::Command, head: &Head) {
+        let Some(ref name) = head.name else {
+            return;
+        };
+        cmd.arg("--output");
+        cmd.arg(name);
+
+        if let Some(ref config) = head.config {
+            cmd.arg("--on");
+
+            cmd.arg(if config.custom_mode.is_some() {
+                "--custom-mode"
+            } else {
+                "--mode"
+            });
+            cmd.arg(format!(
+                "{}x{}@{}Hz",
+                config.width, config.height, config.refresh_rate
+            ));
 
-        for head in heads {
+            cmd.arg("--pos");
+            cmd.arg(format!("{},{}", config.x, config.y));
+
+            cmd.arg("--scale");
+            cmd.arg(format!("{}", self.scale_policy.apply(config)));
+
+            cmd.arg("--transform");
+            cmd.arg(config.transform.to_string());
+
+            cmd.arg("--adaptive-sync");
+            cmd.arg(if config.vrr { "enabled" } else { "disabled" });
+        } else {
+            cmd.arg("--off");
+        }
+    }
+
+    fn run(&self, mut cmd: std::process::Command) -> anyhow::Result<()> {
+        if self.dry_run {
+            info!(command = %command_line(&cmd), "Would execute");
+            return Ok(());
+        }
+        debug!(command = %command_line(&cmd), "Executing");
+        let status = cmd
+            .status()
+            .map_err(|err| spawn_error(&self.executable, err))?;
+        if !status.success() {
+            return Err(failed_error(&self.executable));
+        }
+        Ok(())
+    }
+
+    /// Configure each head with a separate invocation, so that heads that can
+    /// be configured are, even if others fail. Fails if any head failed,
+    /// naming them.
+    fn set_head_config_isolated(&self, heads: &[Head]) -> anyhow::Result<()> {
+        let mut failed = Vec::new();
+        for head in apply_order(heads) {
             let Some(ref name) = head.name else {
                 continue;
             };
-            cmd.arg("--output");
-            cmd.arg(name);
-
-            if let Some(ref config) = head.config {
-                cmd.arg("--on");
+            let mut cmd = self.command();
+            self.head_args(&mut cmd, head);
+            if let Err(err) = self.run(cmd) {
+                warn!(head = name, "Failed to configure head: {:#}", err);
+                failed.push(name.as_str());
+            }
+        }
 
-                cmd.arg("--mode");
-                cmd.arg(format!(
-                    "{}x{}@{}Hz",
-                    config.width, config.height, config.refresh_rate
-                ));
+        if !failed.is_empty() {
+            return Err(failed_error(&self.executable)
+                .context(format!("failed to configure {}", failed.join(", "))));
+        }
+        Ok(())
+    }
 
-                cmd.arg("--pos");
-                cmd.arg(format!("{},{}", config.x, config.y));
-
-                cmd.arg("--scale");
-                cmd.arg(format!("{}", config.scale));
-
-                cmd.arg("--transform");
-                cmd.arg(match config.transform {
-                    1 => "90",
-                    2 => "180",
-                    3 => "270",
-                    4 => "flipped",
-                    5 => "flipped-90",
-                    6 => "flipped-180",
-                    7 => "flipped-270",
-                    _ => "normal",
-                });
-
-                cmd.arg("--adaptive-sync");
-                cmd.arg(if config.vrr { "enabled" } else { "disabled" });
-            } else {
-                cmd.arg("--off");
-            }
+    fn query_heads(&self) -> anyhow::Result<Vec<WlrRandrHead>> {
+        let output = self
+            .command()
+            .arg("--json")
+            .output()
+            .map_err(|err| spawn_error(&self.executable, err))?;
+        if !output.status.success() {
+            return Err(failed_error(&self.executable));
         }
+        parse_output(&self.executable, &output.stdout)
+    }
+}
+
+impl Backend for WlrRandrBackend {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    #[instrument(skip_all, fields(backend = "wlr-randr"))]
+    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+        Ok(make_heads(self.query_heads()?, self.mode_policy))
+    }
+
+    #[instrument(skip_all, fields(backend = "wlr-randr"))]
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+        Ok(self
+            .query_heads()?
+            .into_iter()
+            .map(|head| {
+                let modes = head.modes.iter().map(WlrRandrHeadMode::make_mode).collect();
+                (head.name, modes)
+            })
+            .collect())
+    }
 
-        debug!("Executing {:?}", cmd);
-        if !cmd.status()?.success() {
-            return Err(anyhow::anyhow!("wlr-randr failed"));
+    #[instrument(skip_all, fields(backend = "wlr-randr", heads = heads.len()))]
+    fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
+        if self.isolate {
+            return self.set_head_config_isolated(heads);
         }
 
-        Ok(())
+        let mut cmd = self.command();
+        for head in apply_order(heads) {
+            self.head_args(&mut cmd, head);
+        }
+        self.run(cmd)
     }
 
     fn fallback_head_config(
@@ -82,7 +197,8 @@ impl Backend for WlrRandrBackend {
         active_head_names: &[String],
         inactive_head_names: &[String],
     ) -> anyhow::Result<()> {
-        let mut cmd = std::process::Command::new(&self.executable);
+        let mut available_modes = self.get_available_modes()?;
+        let mut cmd = self.command();
 
         let mut previous_head = None;
         for head in active_head_names {
@@ -90,7 +206,23 @@ impl Backend for WlrRandrBackend {
             cmd.arg(head);
 
             cmd.arg("--on");
-            cmd.arg("--preferred");
+            match available_modes
+                .remove(head)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|m| m.preferred)
+            {
+                Some(AvailableMode { mode, .. }) => {
+                    cmd.arg("--mode");
+                    cmd.arg(format!(
+                        "{}x{}@{}Hz",
+                        mode.width, mode.height, mode.refresh_rate
+                    ));
+                }
+                None => {
+                    cmd.arg("--preferred");
+                }
+            }
 
             if let Some(previous_head) = previous_head {
                 cmd.arg("--right-of");
@@ -116,16 +248,35 @@ impl Backend for WlrRandrBackend {
             cmd.arg("--off");
         }
 
-        debug!("Executing {:?}", cmd);
-        if !cmd.status()?.success() {
-            return Err(anyhow::anyhow!("wlr-randr failed"));
-        }
-
-        Ok(())
+        self.run(cmd)
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Turn the heads reported by wlr-randr into `Head`s. Heads that are enabled,
+/// but report no modes, are left out: some displays (e.g. behind docking
+/// stations) briefly enumerate like this, and treating them as disabled would
+/// disable them on the next restore.
+fn make_heads(heads: Vec<WlrRandrHead>, mode_policy: ModePolicy) -> Vec<Head> {
+    heads
+        .into_iter()
+        .filter(|head| {
+            let unavailable = head.enabled && head.modes.is_empty();
+            if unavailable {
+                warn!(
+                    head = head.name,
+                    "Head is enabled, but reports no modes, leaving it out for now"
+                );
+            }
+            !unavailable
+        })
+        .map(|head| head.make_head(mode_policy))
+        .collect()
+}
+
+// All fields default, so that output of wlr-randr versions lacking some of
+// them can still be parsed
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
 struct WlrRandrHead {
     name: String,
     make: Option<String>,
@@ -138,55 +289,205 @@ struct WlrRandrHead {
     scale: Option<f64>,
     adaptive_sync: Option<bool>,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
 struct WlrRandrHeadMode {
     width: i32,
     height: i32,
     refresh: f64,
-    // preferred: bool,
+    preferred: bool,
     current: bool,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
 struct WlrRandrHeadPosition {
     x: i32,
     y: i32,
 }
+impl WlrRandrHeadMode {
+    fn make_mode(&self) -> AvailableMode {
+        AvailableMode {
+            mode: HeadMode {
+                width: self.width,
+                height: self.height,
+                refresh_rate: self.refresh,
+            },
+            current: self.current,
+            preferred: self.preferred,
+        }
+    }
+}
 impl WlrRandrHead {
-    fn make_head(self) -> Head {
+    /// Whether the current mode is not one the head supports. wlroots lists
+    /// a custom mode after the supported ones, so it is taken to be the
+    /// current mode if that is listed last, is not preferred, and has a
+    /// resolution none of the other modes has.
+    fn has_custom_mode(&self) -> bool {
+        let Some((current, others)) = self.modes.split_last() else {
+            return false;
+        };
+        current.current
+            && !current.preferred
+            && !others.is_empty()
+            && !others
+                .iter()
+                .any(|m| (m.width, m.height) == (current.width, current.height))
+    }
+
+    fn make_head(self, mode_policy: ModePolicy) -> Head {
+        let modes: Vec<AvailableMode> =
+            self.modes.iter().map(WlrRandrHeadMode::make_mode).collect();
+        let custom_mode = self.has_custom_mode();
+        let current_mode = modes.iter().find(|m| m.current).or_else(|| {
+            let mode = mode_policy.pick(&modes)?;
+            if self.enabled {
+                warn!(head = self.name, mode = %mode.mode, ?mode_policy, "No current mode reported, picked one");
+            }
+            Some(mode)
+        });
+
         Head {
             name: Some(self.name),
             make: self.make.unwrap_or_default(),
             model: self.model.unwrap_or_default(),
             serial: self.serial.unwrap_or_default(),
-            config: if self.enabled && !self.modes.is_empty() {
-                let mode_idx = self
-                    .modes
-                    .iter()
-                    .position(|m| m.current)
-                    .unwrap_or_default();
-                let mode = self.modes.get(mode_idx).unwrap();
-                Some(HeadConfig {
+            config: match current_mode {
+                Some(AvailableMode { mode, .. }) if self.enabled => Some(HeadConfig {
                     x: self.position.as_ref().map(|p| p.x).unwrap_or(0),
                     y: self.position.as_ref().map(|p| p.y).unwrap_or(0),
                     width: mode.width,
                     height: mode.height,
-                    refresh_rate: mode.refresh,
+                    refresh_rate: mode.refresh_rate,
                     scale: self.scale.unwrap_or(1.0),
                     vrr: self.adaptive_sync.unwrap_or(false),
-                    transform: match self.transform.as_deref().unwrap_or_default() {
-                        "90" => 1,
-                        "180" => 2,
-                        "270" => 3,
-                        "flipped" => 4,
-                        "flipped-90" => 5,
-                        "flipped-180" => 6,
-                        "flipped-270" => 7,
-                        _ => 0,
-                    },
-                })
-            } else {
-                None
+                    transform: self
+                        .transform
+                        .as_deref()
+                        .and_then(|t| t.parse().ok())
+                        .unwrap_or_default(),
+                    custom_mode: custom_mode
+                        .then(|| format!("{}x{}@{}", mode.width, mode.height, mode.refresh_rate)),
+                    bitdepth: None,
+                    cm: None,
+                    sdr_brightness: None,
+                    sdr_saturation: None,
+                    tearing: None,
+                    mirror_of: None,
+                    max_refresh: false,
+                    auto_position: false,
+                    mode_spec: None,
+                }),
+                _ => None,
             },
+            reserved: None,
+            extra_commands: Vec::new(),
+            create_if_missing: false,
+            primary: false,
+            mirror_group: None,
+            mirror_source: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_heads(json: &str) -> Vec<WlrRandrHead> {
+        parse_output("wlr-randr", json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parse_superset_of_fields() {
+        // Fields of newer wlr-randr versions, and some made up, are ignored
+        let heads = parse_heads(
+            r#"[{
+                "name": "DP-1",
+                "description": "Dell Inc. DELL U2720Q ABC123 (DP-1)",
+                "make": "Dell Inc.",
+                "model": "DELL U2720Q",
+                "serial": "ABC123",
+                "physical_size": {"width": 600, "height": 340},
+                "enabled": true,
+                "modes": [
+                    {"width": 3840, "height": 2160, "refresh": 60.0, "preferred": true,
+                     "current": true, "picture_aspect_ratio": "16:9"}
+                ],
+                "position": {"x": 1504, "y": 0, "z": 1},
+                "transform": "90",
+                "scale": 1.5,
+                "adaptive_sync": true,
+                "future_field": {"nested": [1, 2, 3]}
+            }]"#,
+        );
+        let head = heads
+            .into_iter()
+            .next()
+            .unwrap()
+            .make_head(ModePolicy::First);
+        assert_eq!(head.make, "Dell Inc.");
+        let config = head.config.unwrap();
+        assert_eq!((config.width, config.height, config.x), (3840, 2160, 1504));
+        assert_eq!(config.transform, crate::types::Transform::Rotate90);
+        assert!(config.vrr);
+    }
+
+    #[test]
+    fn enabled_head_without_modes_is_left_out() {
+        let heads = make_heads(
+            parse_heads(
+                r#"[{
+                    "name": "DP-1",
+                    "enabled": true,
+                    "modes": []
+                }, {
+                    "name": "DP-2",
+                    "enabled": false,
+                    "modes": []
+                }, {
+                    "name": "DP-3",
+                    "enabled": true,
+                    "modes": [
+                        {"width": 1920, "height": 1080, "refresh": 60.0, "current": true}
+                    ]
+                }]"#,
+            ),
+            ModePolicy::First,
+        );
+        let names: Vec<&str> = heads.iter().map(|h| h.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["DP-2", "DP-3"]);
+        assert!(heads[0].config.is_none());
+        assert!(heads[1].config.is_some());
+    }
+
+    #[test]
+    fn custom_mode_is_detected() {
+        let heads = parse_heads(
+            r#"[{
+                "name": "DP-1",
+                "enabled": true,
+                "modes": [
+                    {"width": 3440, "height": 1440, "refresh": 59.97, "preferred": true},
+                    {"width": 2560, "height": 1080, "refresh": 60.0},
+                    {"width": 3440, "height": 1440, "refresh": 100.0, "current": true}
+                ]
+            }, {
+                "name": "DP-2",
+                "enabled": true,
+                "modes": [
+                    {"width": 2560, "height": 1440, "refresh": 144.0, "preferred": true},
+                    {"width": 2560, "height": 1440, "refresh": 60.0},
+                    {"width": 3000, "height": 1250, "refresh": 75.0, "current": true}
+                ]
+            }]"#,
+        );
+        let heads: Vec<Head> = heads
+            .into_iter()
+            .map(|h| h.make_head(ModePolicy::First))
+            .collect();
+        let config = |idx: usize| heads[idx].config.as_ref().unwrap();
+        assert_eq!(config(0).custom_mode, None);
+        assert_eq!(config(1).custom_mode.as_deref(), Some("3000x1250@75"));
+        assert_eq!((config(1).width, config(1).height), (3000, 1250));
+    }
+}
@@ -1,14 +1,63 @@
-use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use clap::{Parser, Subcommand};
-use log::{debug, error};
-use serde::Deserialize;
+use clap::{CommandFactory, Parser, Subcommand};
+use tracing::{debug, error};
 
 use wlscsr::{
-    backend::{Backend, HyprctlBackend, WlrRandrBackend},
-    types::Head,
+    arrange::{ArrangeStrategy, arrange_heads},
+    backend::{
+        Backend, HyprctlBackend, PretendBackend, ReadOnlyBackend, StableBackend, WlrRandrBackend,
+    },
+    bundle::{export_all, import_all},
+    config::{ConfigFile, read_config_file},
+    dirs::{set_extra_config_file, set_portable, set_profile_dir},
+    doctor::{CheckStatus, diagnose},
+    error::Error,
+    events::read_events,
+    format::Format,
+    info::Info,
+    lock::{lock_state_dir, set_lock_timeout},
+    plan::{Plan, plan_head_config},
+    presentation::{presentation_off, presentation_on},
+    profile::{
+        DEFAULT_PROFILE, Explanation, apply_head_config, capture_head_config, check_saved_profiles,
+        current_heads, explain_head_config, force_head_config, heads_hash, keep_all_but_positions,
+        load_head_config_with_fallbacks, match_head_config, newest_profile, prefer_current_mode,
+        profile_path, rename_profile, replace_serial, restore_backup, restore_workspaces,
+        run_hooks, save_head_config, scale_heads,
+    },
+    schema::{PROFILE_SCHEMA, validate_profile},
+    service::Health,
+    source::read_profile_source,
+    status::{LastApplied, Status},
+    toggle::{disable_head, enable_head, rotate_head},
+    types::{Anonymize, Head, Transform},
+    undo::{undo, undoable},
+    watch::{WatchOptions, wait_for_head, watch},
 };
 
+/// Exit status when no saved config was found for the connected monitors
+const EXIT_PROFILE_NOT_FOUND: i32 = 3;
+/// Exit status when the backend could not be run or failed, e.g. to apply a
+/// config
+const EXIT_BACKEND_FAILED: i32 = 4;
+/// Exit status when a config does not match the connected monitors
+const EXIT_CONFIG_MISMATCH: i32 = 5;
+/// Exit status of `save --only-if-changed` when nothing was saved
+const EXIT_UNCHANGED: i32 = 6;
+
+/// Set by `--quiet` to suppress human readable output on stdout
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Like `println!`, but prints nothing if `--quiet` is given
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
 #[derive(Parser, Debug)]
 #[clap(
     name = "wlscsr",
@@ -16,18 +65,122 @@ use wlscsr::{
     about = "Save and restore monitor configurations in Hyprland"
 )]
 pub struct Cli {
-    #[clap(long)]
+    #[clap(long, env = "WLSCSR_BACKEND")]
     #[arg(value_enum)]
     backend: Option<BackendType>,
 
     #[clap(long)]
     executable: Option<String>,
 
+    /// Additional arguments, separated by white space, passed to the backend
+    /// executable on every invocation before any others. Use with care, they
+    /// are not checked in any way.
+    #[clap(long, allow_hyphen_values = true)]
+    backend_args: Option<String>,
+
+    /// Read the connected monitors from a JSON file of heads instead of
+    /// querying the backend, for testing without the actual hardware.
+    /// Configurations are still applied through the backend.
+    #[clap(long, hide = true)]
+    pretend_heads: Option<std::path::PathBuf>,
+
+    /// Name of the profile to save, restore or show [default: default, or
+    /// with `restore_newest_profile`, the newest one when restoring]
+    #[clap(long, env = "WLSCSR_PROFILE")]
+    profile: Option<String>,
+
+    /// Do not print human readable output, only JSON when requested and
+    /// errors
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Keep config and state next to the executable instead of in the XDG
+    /// base directories (implied if a wlscsr.portable file exists there)
+    #[clap(long, global = true)]
+    portable: bool,
+
+    /// Configure monitors with Hyprland's newer monitorv2 blocks (hyprctl
+    /// backend only), as does `monitorv2 = true` in the config
+    #[clap(long, global = true)]
+    monitorv2: bool,
+
+    /// Signature of the Hyprland instance to configure, instead of the one
+    /// in HYPRLAND_INSTANCE_SIGNATURE (hyprctl backend only)
+    #[clap(long, global = true)]
+    instance: Option<String>,
+
+    /// With the wlr-randr backend, configure each monitor with a separate
+    /// invocation, so that one monitor failing does not keep the others from
+    /// being configured
+    #[clap(long, global = true)]
+    isolate: bool,
+
+    /// Read this config file after the system-wide and the user's one,
+    /// overriding their settings
+    #[clap(long, global = true)]
+    config: Option<std::path::PathBuf>,
+
+    /// Look for saved configurations in this directory before the state
+    /// directory, and save them here instead. Can also be set, for restoring
+    /// only, with `profile_dir` in the config file.
+    #[clap(long, global = true)]
+    profile_dir: Option<std::path::PathBuf>,
+
+    /// Only query monitors, failing any command that would change them. Can
+    /// also be set with `read_only = true` in the config file.
+    #[clap(long, global = true)]
+    read_only: bool,
+
+    /// Print the hash identifying the connected monitors, as used in the names
+    /// of saved configuration files, to stderr before running the command.
+    /// Not supported by commands that do not read the config file, such as
+    /// doctor or schema
+    #[clap(long, global = true)]
+    print_hash: bool,
+
+    /// Do not lock the state directory while changing monitors or saved
+    /// configurations
+    #[clap(long, global = true)]
+    no_lock: bool,
+
+    /// Seconds to wait for another wlscsr process to release the lock on the
+    /// state directory
+    #[clap(long, global = true, default_value_t = 10, conflicts_with = "no_lock")]
+    lock_timeout: u64,
+
+    /// Print the configuration as read from the config file, including
+    /// defaults, as TOML (or JSON with --dump-config=json) and exit
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml"
+    )]
+    dump_config: Option<DumpFormat>,
+
+    /// Format in which errors are printed to stderr
+    #[clap(long, value_enum, default_value_t = ErrorFormat::Plain)]
+    error_format: ErrorFormat,
+
+    /// Required unless --dump-config is given
     #[clap(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
 }
 
-#[derive(Debug, Clone, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum ErrorFormat {
+    Plain,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum DumpFormat {
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
 enum BackendType {
     WlrRandr,
     Hyprctl,
@@ -36,13 +189,194 @@ enum BackendType {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Save configuration screen configuration
-    Save,
+    Save(SaveOptions),
+
+    /// Print the screen configuration to stdout as `save` would save it,
+    /// e.g. to modify it and pipe it to `apply -`
+    Capture(CaptureOptions),
+
+    /// Bring back the version of the profile from before it was last
+    /// overwritten by save
+    RestoreBackup,
 
     /// Restore previously save screen configuration
     Restore(RestoreOptions),
 
     /// Display information on connected monitors
-    Info,
+    Info(InfoOptions),
+
+    /// Apply a screen configuration from a file or URL to the connected
+    /// monitors, provided it matches them
+    Apply(ApplyOptions),
+
+    /// Apply a screen configuration from a file or URL to the connected
+    /// monitors even if it was saved for other monitors, pairing them up by
+    /// order. For testing only: the saved modes may not be supported.
+    ApplyForce(ApplyOptions),
+
+    /// Run a D-Bus service on the session bus to save and restore on request
+    Serve,
+
+    /// Watch for monitors being connected or disconnected and restore the
+    /// saved configuration whenever they have settled
+    Watch(WatchCliOptions),
+
+    /// Show whether the saved configuration is active, querying the D-Bus
+    /// service if it is running
+    Status(StatusOptions),
+
+    /// Show whether the D-Bus service is running, how long it has been and
+    /// what it last did, failing if it is not running
+    Health(StatusOptions),
+
+    /// Apply the default configuration, as restore --fallback-to-default does
+    /// when no saved configuration matches
+    #[command(name = "apply-default")]
+    Default,
+
+    /// List all saved profiles, checking for files that can never be found
+    /// or that shadow each other
+    Profiles,
+
+    /// Check the backend, config file and saved profiles for problems
+    Doctor,
+
+    /// Write all saved profiles and the config file into a .tar.gz bundle
+    ExportAll(ExportOptions),
+
+    /// Restore saved profiles and the config file from a bundle written by
+    /// export-all
+    ImportAll(ImportOptions),
+
+    /// Turn on a monitor, with the configuration it had when it was last
+    /// turned off with `disable`
+    Enable(ToggleOptions),
+
+    /// Turn off a monitor, leaving all others as they are
+    Disable(ToggleOptions),
+
+    /// Rotate a monitor, leaving everything else as it is
+    Rotate(RotateOptions),
+
+    /// Mirror the primary monitor onto all others with VRR off, e.g. for a
+    /// projector, or go back to the saved configuration with --off
+    Presentation(PresentationOptions),
+
+    /// Move the enabled monitors next to each other so that they do not
+    /// overlap, keeping their modes, without saving anything
+    Arrange(ArrangeOptions),
+
+    /// Show what the daemon (serve or watch) did recently
+    Log(LogOptions),
+
+    /// Go back to the layout before the last restore, apply, apply-default,
+    /// arrange or presentation
+    Undo(UndoOptions),
+
+    /// Rename a saved profile of the connected monitors
+    Rename(RenameOptions),
+
+    /// Replace the serial number of a monitor in all saved profiles, e.g.
+    /// after exchanging it for another one of the same model
+    ReplaceSerial(ReplaceSerialOptions),
+
+    /// Check a saved screen configuration file for errors, e.g. after editing
+    /// it by hand
+    Validate(ValidateOptions),
+
+    /// Print the JSON Schema of saved screen configuration files
+    Schema,
+
+    /// Print the modes supported by a monitor, one per line, e.g. as
+    /// 1920x1080@60Hz
+    ListModes(ListModesOptions),
+
+    /// Query the monitors through every backend and show where they report
+    /// them differently, for debugging profiles that do not match across
+    /// backends
+    #[clap(hide = true)]
+    CompareBackends(CompareBackendsOptions),
+}
+
+impl Commands {
+    /// Whether the command changes monitors or saved configurations, and so
+    /// must not run concurrently with others that do
+    fn changes_state(&self) -> bool {
+        match self {
+            Commands::Restore(opt) => !opt.explain && !opt.plan,
+            Commands::Arrange(opt) => !opt.dry_run,
+            Commands::ReplaceSerial(opt) => !opt.dry_run,
+            Commands::Save(_)
+            | Commands::Apply(_)
+            | Commands::ApplyForce(_)
+            | Commands::Default
+            | Commands::ImportAll(_)
+            | Commands::Enable(_)
+            | Commands::Disable(_)
+            | Commands::Rotate(_)
+            | Commands::Presentation(_)
+            | Commands::Undo(_)
+            | Commands::Rename(_)
+            | Commands::RestoreBackup => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the command works regardless of problems with the config file,
+    /// and so runs without reading it
+    fn runs_without_config(&self) -> bool {
+        matches!(
+            self,
+            Commands::Doctor
+                | Commands::ExportAll(_)
+                | Commands::Log(_)
+                | Commands::Health(_)
+                | Commands::ImportAll(_)
+                | Commands::Validate(_)
+                | Commands::Schema
+                | Commands::ListModes(_)
+        )
+    }
+}
+
+#[derive(Parser, Debug)]
+struct SaveOptions {
+    /// Overwrite the profile if it has been saved before
+    #[clap(long)]
+    force: bool,
+
+    /// Leave the file untouched and exit with status 6 if the configuration
+    /// is the same as the saved one. Combine with --force to overwrite it
+    /// otherwise
+    #[clap(long)]
+    only_if_changed: bool,
+
+    /// Write compact JSON instead of pretty-printed JSON, as does
+    /// `compact_json = true` in the config file
+    #[clap(long)]
+    compact: bool,
+
+    /// Save exact positions even if `auto_positions = true` is set in the
+    /// config file
+    #[clap(long)]
+    absolute_positions: bool,
+}
+
+#[derive(Parser, Debug)]
+struct CaptureOptions {
+    /// Print compact JSON instead of pretty-printed JSON
+    #[clap(long)]
+    compact: bool,
+
+    /// Format to print the configuration in, json or yaml (which requires the
+    /// yaml feature)
+    #[clap(long, default_value = "json")]
+    format: Format,
+
+    /// Print exact positions even if `auto_positions = true` is set in the
+    /// config file
+    #[clap(long)]
+    absolute_positions: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -50,202 +384,1269 @@ struct RestoreOptions {
     /// If no saved configuration is found, apply a default configuration as default
     #[clap(long)]
     fallback_to_default: bool,
+
+    /// Ask for confirmation after applying the configuration, and revert to the
+    /// previous configuration if none is given in time
+    #[clap(long)]
+    interactive: bool,
+
+    /// Seconds to wait for confirmation with --interactive
+    #[clap(long, default_value_t = 10, requires = "interactive")]
+    timeout: u64,
+
+    /// Explain whether and why the saved configuration matches the connected
+    /// monitors, without restoring it
+    #[clap(long)]
+    explain: bool,
+
+    /// Move workspaces back to the monitors they were on before restoring
+    #[clap(long)]
+    preserve_workspaces: bool,
+
+    /// Keep the mode monitors are currently running in, only restoring their
+    /// position, scale, transform and so on
+    #[clap(long)]
+    prefer_current_mode: bool,
+
+    /// Multiply the saved scale of every monitor by this factor, without
+    /// changing the saved configuration
+    #[clap(long)]
+    scale_factor: Option<f64>,
+
+    /// Only move monitors to their saved positions, keeping their current
+    /// mode, scale and everything else, and leaving them on or off
+    #[clap(long, conflicts_with_all = ["prefer_current_mode", "scale_factor"])]
+    positions_only: bool,
+
+    /// Leave monitors ignored because of a closed lid alone instead of
+    /// turning them off, as does `leave_ignored_heads = true` in the config
+    /// file
+    #[clap(long)]
+    no_ignored_disable: bool,
+
+    /// Show what restoring would change for each monitor, and any warnings,
+    /// without restoring anything
+    #[clap(long, conflicts_with_all = ["explain", "interactive"])]
+    plan: bool,
+
+    /// Print the plan as JSON
+    #[clap(long, requires = "plan")]
+    json: bool,
+
+    #[command(flatten)]
+    wait: WaitOptions,
+}
+
+#[derive(Parser, Debug)]
+struct ToggleOptions {
+    /// Name of the monitor, e.g. eDP-1
+    head: String,
+}
+
+#[derive(Parser, Debug)]
+struct RenameOptions {
+    /// Current name of the profile
+    from: String,
+
+    /// New name of the profile
+    to: String,
+
+    /// Rename the profile saved for the monitors with this hash, as listed by
+    /// `profiles`, instead of the connected ones
+    #[clap(long)]
+    hash: Option<String>,
+
+    /// Overwrite the profile if it has been saved before under the new name
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ReplaceSerialOptions {
+    /// Make of the monitor
+    make: String,
+
+    /// Model of the monitor
+    model: String,
+
+    /// Serial number the profiles were saved with
+    old: String,
+
+    /// Serial number of the new monitor
+    new: String,
+
+    /// Only print which profiles would be changed
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ListModesOptions {
+    /// Name of the monitor, e.g. DP-1
+    #[clap(required_unless_present = "all", conflicts_with = "all")]
+    name: Option<String>,
+
+    /// List the modes of all monitors, each line starting with the name of
+    /// the monitor
+    #[clap(long)]
+    all: bool,
+}
+
+#[derive(Parser, Debug)]
+struct CompareBackendsOptions {
+    /// wlr-randr executable
+    #[clap(long)]
+    wlr_randr: Option<String>,
+
+    /// hyprctl executable
+    #[clap(long)]
+    hyprctl: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ValidateOptions {
+    /// Path of the saved screen configuration
+    path: std::path::PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct PresentationOptions {
+    /// Turn presentation mode off by restoring the saved configuration
+    #[clap(long)]
+    off: bool,
+}
+
+#[derive(Parser, Debug)]
+struct UndoOptions {
+    /// Number of changes to undo
+    #[clap(long, default_value_t = 1)]
+    steps: usize,
+}
+
+#[derive(Parser, Debug)]
+struct LogOptions {
+    /// Only show events of this long ago or later, e.g. 1h or 2days
+    #[clap(long)]
+    since: Option<humantime::Duration>,
+
+    /// Print the events as JSON lines
+    #[clap(long)]
+    json: bool,
 }
 
-#[derive(Deserialize, Debug, Default)]
-struct ConfigFile {
-    lid: Vec<LidConfig>,
+#[derive(Parser, Debug)]
+struct ArrangeOptions {
+    /// horizontal, vertical or grid
+    #[clap(default_value = "horizontal")]
+    strategy: ArrangeStrategy,
+
+    /// Only print the new positions, without applying them
+    #[clap(long)]
+    dry_run: bool,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-struct LidConfig {
-    file: PathBuf,
+#[derive(Parser, Debug)]
+struct RotateOptions {
+    /// Name of the monitor, e.g. DP-1
     head: String,
+
+    /// 0 to 7, normal, 90, 180, 270, flipped, flipped-90, flipped-180,
+    /// flipped-270, left, right or inverted
+    transform: Transform,
+}
+
+#[derive(Parser, Debug)]
+struct ExportOptions {
+    /// Path of the bundle
+    path: std::path::PathBuf,
+
+    /// Format of the profiles in the bundle, json or yaml (which requires the
+    /// yaml feature)
+    #[clap(long, default_value = "json")]
+    format: Format,
+
+    #[command(flatten)]
+    anonymize: AnonymizeOptions,
+}
+
+#[derive(clap::Args, Debug)]
+struct AnonymizeOptions {
+    /// Replace serial numbers, or with --anonymize=all also makes and models,
+    /// with placeholders, e.g. to share the result publicly
+    #[clap(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "serials"
+    )]
+    anonymize: Option<Anonymize>,
+}
+
+#[derive(Parser, Debug)]
+struct ImportOptions {
+    /// Path of the bundle
+    path: std::path::PathBuf,
+
+    /// Replace existing files with different contents
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ApplyOptions {
+    /// Path or http(s) URL of a saved screen configuration, or - to read it
+    /// from stdin
+    source: String,
+
+    /// Format of the configuration, json or yaml (which requires the yaml
+    /// feature). By default, YAML is read from .yaml and .yml files and JSON
+    /// from anything else.
+    #[clap(long)]
+    format: Option<Format>,
+}
+
+impl ApplyOptions {
+    fn format(&self) -> Format {
+        self.format
+            .unwrap_or_else(|| Format::from_path(&self.source))
+    }
+}
+
+#[derive(Parser, Debug)]
+struct InfoOptions {
+    /// List the modes supported by each monitor
+    #[clap(long)]
+    modes: bool,
+
+    /// Print the information as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Only show this monitor, given as connector name or part of its make,
+    /// model or serial number. Can be given more than once.
+    #[clap(long)]
+    monitor: Vec<String>,
+
+    #[command(flatten)]
+    anonymize: AnonymizeOptions,
+}
+
+#[derive(clap::Args, Debug)]
+struct WaitOptions {
+    /// Wait until this monitor is connected before doing anything, given as
+    /// connector name (e.g. DP-1), serial number, or make, model and serial
+    /// separated by spaces
+    #[clap(long)]
+    wait_for: Option<String>,
+
+    /// Seconds to wait with --wait-for before giving up
+    #[clap(long, default_value_t = 30, requires = "wait_for")]
+    wait_timeout: u64,
+}
+
+impl WaitOptions {
+    fn wait(&self, backend: &dyn Backend) -> anyhow::Result<()> {
+        match self.wait_for {
+            Some(ref wanted) => wait_for_head(
+                backend,
+                wanted,
+                std::time::Duration::from_secs(self.wait_timeout),
+                std::time::Duration::from_millis(500),
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+struct WatchCliOptions {
+    /// Exit after the first restore, which happens as soon as the connected
+    /// monitors have settled
+    #[clap(long)]
+    once: bool,
+
+    /// Milliseconds between queries of the connected monitors
+    #[clap(long, default_value_t = 500)]
+    interval: u64,
+
+    /// Milliseconds the connected monitors must stay the same before restoring
+    #[clap(long, default_value_t = 2000)]
+    settle: u64,
+
+    /// Only log which profile would be restored and the commands that would
+    /// be run (with RUST_LOG=info), without changing the monitors
+    #[clap(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    wait: WaitOptions,
+}
+
+#[derive(Parser, Debug)]
+struct StatusOptions {
+    /// Print the status as JSON
+    #[clap(long)]
+    json: bool,
 }
 
 fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
 
     let cli = Cli::parse();
-    let backend: Box<dyn Backend> = match cli.backend.unwrap_or(BackendType::WlrRandr) {
-        BackendType::WlrRandr => Box::new(WlrRandrBackend::new(
-            cli.executable
-                .as_deref()
-                .or(option_env!("STD_EXECUTABLE_WLR_RANDR"))
-                .unwrap_or("wlr-randr")
-                .to_string(),
-        )),
-        BackendType::Hyprctl => Box::new(HyprctlBackend::new(
-            cli.executable
-                .as_deref()
-                .or(option_env!("STD_EXECUTABLE_HYPRCTL"))
-                .unwrap_or("hyprctl")
-                .to_string(),
-        )),
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    set_portable(cli.portable);
+    set_profile_dir(cli.profile_dir.clone());
+    set_extra_config_file(cli.config.clone());
+    set_lock_timeout((!cli.no_lock).then(|| std::time::Duration::from_secs(cli.lock_timeout)));
+    let code = match run(&cli) {
+        Ok(code) => code,
+        Err(err) => {
+            let typed = Error::find(&err);
+            if cli.error_format == ErrorFormat::Json {
+                let report = serde_json::json!({
+                    "error": typed.map(Error::kind).unwrap_or("other"),
+                    "message": format!("{:#}", err),
+                    "path": typed.map(Error::path),
+                });
+                eprintln!("{}", report);
+            } else {
+                eprintln!("Error: {:?}", err);
+            }
+            typed.map(exit_code).unwrap_or(1)
+        }
     };
+    if code != 0 {
+        std::process::exit(code);
+    }
 
-    let config = read_config_file()?;
-    debug!("Config: {:?}", &config);
+    Ok(())
+}
 
-    // Find out which heads should be ignored because of a closed lid
-    let ignored_head_names: std::collections::HashSet<String> = config
-        .lid
-        .iter()
-        .filter_map(|LidConfig { file, head }| {
-            let closed = std::fs::read(file)
-                .ok()
-                .map(|contents| contents.trim_ascii().ends_with(b"closed"))
-                .unwrap_or(false);
-            if closed { Some(head.to_string()) } else { None }
-        })
+/// Exit status for a failure caused by `err`, so that scripts can tell the
+/// most common failures apart
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::ProfileNotFound { .. } => EXIT_PROFILE_NOT_FOUND,
+        Error::ExecutableNotFound { .. } | Error::BackendFailed { .. } => EXIT_BACKEND_FAILED,
+        Error::ConfigMismatch { .. } => EXIT_CONFIG_MISMATCH,
+        Error::ProfileExists { .. } | Error::ReadOnly | Error::Locked { .. } => 1,
+    }
+}
+
+/// Run the command, returning the exit status if it succeeded
+fn run(cli: &Cli) -> anyhow::Result<i32> {
+    if let Some(format) = cli.dump_config {
+        let config = read_config_file()?;
+        match format {
+            DumpFormat::Toml => print!("{}", toml::to_string(&config)?),
+            DumpFormat::Json => println!("{}", serde_json::to_string_pretty(&config)?),
+        }
+        return Ok(0);
+    }
+    let Some(ref command) = cli.command else {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required",
+            )
+            .exit();
+    };
+
+    let backend_args: Vec<String> = cli
+        .backend_args
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
         .collect();
+    // The config is only needed for the backend's policies here, errors
+    // are reported below
+    let config = read_config_file();
+    let scale_policy = config.as_ref().ok().and_then(|config| config.scale_policy);
+    let mode_policy = config
+        .as_ref()
+        .map(|config| config.mode_policy)
+        .unwrap_or_default();
+    let monitorv2 = cli.monitorv2 || config.as_ref().is_ok_and(|config| config.monitorv2);
+    let dry_run = matches!(command, Commands::Watch(opt) if opt.dry_run);
+    let mut backend: Box<dyn Backend> = match cli.backend.unwrap_or(BackendType::WlrRandr) {
+        BackendType::WlrRandr => Box::new(
+            WlrRandrBackend::new(
+                cli.executable
+                    .as_deref()
+                    .or(option_env!("STD_EXECUTABLE_WLR_RANDR"))
+                    .unwrap_or("wlr-randr")
+                    .to_string(),
+            )
+            .with_args(backend_args)
+            .with_scale_policy(scale_policy)
+            .with_mode_policy(mode_policy)
+            .with_isolate(cli.isolate)
+            .with_dry_run(dry_run),
+        ),
+        BackendType::Hyprctl => Box::new(
+            HyprctlBackend::new(
+                cli.executable
+                    .as_deref()
+                    .or(option_env!("STD_EXECUTABLE_HYPRCTL"))
+                    .unwrap_or("hyprctl")
+                    .to_string(),
+            )
+            .with_args(backend_args)
+            .with_monitorv2(monitorv2)
+            .with_instance(cli.instance.clone())
+            .with_scale_policy(scale_policy)
+            .with_mode_policy(mode_policy)
+            .with_dry_run(dry_run),
+        ),
+    };
+    if let Some(ref path) = cli.pretend_heads {
+        debug!(path = %path.display(), "Pretending heads");
+        backend = Box::new(PretendBackend::from_file(backend, path)?);
+    }
+
+    // The hash depends on the config file
+    if cli.print_hash && command.runs_without_config() {
+        return Err(anyhow::anyhow!(
+            "--print-hash is not supported by doctor, export-all, import-all, log, health, validate, schema and list-modes; try `wlscsr --print-hash info`"
+        ));
+    }
+
+    // Commands changing monitors or saved configurations take turns with
+    // each other and with the daemon
+    let _lock = if command.changes_state() {
+        Some(lock_state_dir()?)
+    } else {
+        None
+    };
+
+    // These must work regardless of problems with the config file
+    match command {
+        Commands::Doctor => {
+            doctor(backend.as_ref())?;
+            return Ok(0);
+        }
+        Commands::ExportAll(opt) => {
+            let count = export_all(&opt.path, opt.anonymize.anonymize, opt.format)?;
+            say!("Exported {} profiles to {}", count, opt.path.display());
+            return Ok(0);
+        }
+        Commands::Log(opt) => {
+            let since = opt
+                .since
+                .map(|since| {
+                    std::time::SystemTime::now()
+                        .checked_sub(*since)
+                        .ok_or_else(|| anyhow::anyhow!("--since {} is too long ago", since))
+                })
+                .transpose()?;
+            for event in read_events(since)? {
+                if opt.json {
+                    println!("{}", serde_json::to_string(&event)?);
+                } else {
+                    say!(
+                        "{} {}",
+                        humantime::format_rfc3339_seconds(event.time()),
+                        event.kind
+                    );
+                }
+            }
+            return Ok(0);
+        }
+        Commands::Health(opt) => {
+            let health = wlscsr::service::query_health()
+                .map_err(|err| err.context("D-Bus service not available"))?;
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&health)?);
+            } else {
+                print_health(&health);
+            }
+            return Ok(0);
+        }
+        Commands::ImportAll(opt) => {
+            let count = import_all(&opt.path, opt.force)?;
+            say!("Imported {} profiles from {}", count, opt.path.display());
+            return Ok(0);
+        }
+        Commands::Validate(opt) => {
+            let errors = validate_profile(&std::fs::read(&opt.path)?);
+            for err in &errors {
+                say!("{}: {}", opt.path.display(), err);
+            }
+            if !errors.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{} is invalid ({} errors)",
+                    opt.path.display(),
+                    errors.len()
+                ));
+            }
+            say!("{} is valid", opt.path.display());
+            return Ok(0);
+        }
+        Commands::Schema => {
+            print!("{}", PROFILE_SCHEMA);
+            return Ok(0);
+        }
+        Commands::ListModes(opt) => {
+            list_modes(backend.as_ref(), opt.name.as_deref())?;
+            return Ok(0);
+        }
+        _ => (),
+    }
 
-    // Get all heads from backend and sort
-    let mut ignored_heads = Vec::new();
-    let mut heads: Vec<Head> = backend
-        .get_all_heads()?
-        .into_iter()
-        .filter_map(|h| {
-            if h.name
-                .as_ref()
-                .map(|name| ignored_head_names.contains(name))
-                .unwrap_or(false)
-            {
-                ignored_heads.push(h);
+    let mut config = config?;
+    debug!(?config, "Loaded config");
+    if cli.read_only || config.read_only {
+        debug!("Read-only mode");
+        backend = Box::new(ReadOnlyBackend::new(backend));
+    }
+    // The daemon reacts to heads being connected, which may leave them in an
+    // inconsistent state for a moment
+    if matches!(command, Commands::Serve | Commands::Watch(_)) && config.stable_reads.timeout_ms > 0
+    {
+        backend = Box::new(StableBackend::new(
+            backend,
+            std::time::Duration::from_millis(config.stable_reads.interval_ms),
+            std::time::Duration::from_millis(config.stable_reads.timeout_ms),
+        ));
+    }
+    if cli.print_hash {
+        let (heads, _) = current_heads(backend.as_ref(), &config)?;
+        eprintln!("{}", heads_hash(&heads, &config));
+    }
+
+    let profile = cli
+        .profile
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+    match command {
+        Commands::Save(opt) => {
+            config.compact_json |= opt.compact;
+            config.auto_positions &= !opt.absolute_positions;
+            let (heads, _) = current_heads(backend.as_ref(), &config)?;
+            let path = save_head_config(
+                backend.as_ref(),
+                &heads,
+                &profile,
+                &config,
+                opt.force,
+                opt.only_if_changed,
+            )?;
+            if path.is_none() {
+                say!("Screen config unchanged, not saving it");
+                return Ok(EXIT_UNCHANGED);
+            }
+        }
+        Commands::Capture(opt) => {
+            config.compact_json |= opt.compact;
+            config.auto_positions &= !opt.absolute_positions;
+            let (heads, _) = current_heads(backend.as_ref(), &config)?;
+            println!(
+                "{}",
+                capture_head_config(backend.as_ref(), &heads, &profile, &config, opt.format)?
+            );
+        }
+        Commands::CompareBackends(opt) => {
+            let backends: [(&str, Box<dyn Backend>); 2] = [
+                (
+                    "wlr-randr",
+                    Box::new(WlrRandrBackend::new(
+                        opt.wlr_randr
+                            .as_deref()
+                            .or(option_env!("STD_EXECUTABLE_WLR_RANDR"))
+                            .unwrap_or("wlr-randr")
+                            .to_string(),
+                    )),
+                ),
+                (
+                    "hyprctl",
+                    Box::new(
+                        HyprctlBackend::new(
+                            opt.hyprctl
+                                .as_deref()
+                                .or(option_env!("STD_EXECUTABLE_HYPRCTL"))
+                                .unwrap_or("hyprctl")
+                                .to_string(),
+                        )
+                        .with_instance(cli.instance.clone()),
+                    ),
+                ),
+            ];
+            compare_backends(&backends, &config);
+        }
+        Commands::RestoreBackup => {
+            let (heads, _) = current_heads(backend.as_ref(), &config)?;
+            let path = restore_backup(&heads, &profile, &config)?;
+            say!("Restored previous version of {}", path.display());
+        }
+        Commands::Restore(opt) if opt.explain => {
+            let (heads, ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            print_explanation(
+                &explain_head_config(&heads, &ignored_heads, &profile, &config)?,
+                &heads,
+            );
+        }
+        Commands::Restore(opt) if opt.plan => {
+            config.leave_ignored_heads |= opt.no_ignored_disable;
+            let (heads, ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            let profile = match cli.profile {
+                None if config.restore_newest_profile => {
+                    newest_profile(&heads, &config)?.unwrap_or(profile)
+                }
+                _ => profile,
+            };
+            let (profile, mut saved) =
+                load_head_config_with_fallbacks(&heads, &ignored_heads, &profile, &config)?;
+            if opt.prefer_current_mode {
+                prefer_current_mode(&mut saved, &heads);
+            }
+            if let Some(factor) = opt.scale_factor {
+                scale_heads(&mut saved, factor);
+            }
+            if opt.positions_only {
+                keep_all_but_positions(&mut saved, &heads);
+            }
+            let plan = plan_head_config(
+                backend.as_ref(),
+                &profile,
+                &saved,
+                &heads,
+                &ignored_heads,
+                &config,
+            )?;
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else {
+                print_plan(&plan);
+            }
+        }
+        Commands::Restore(opt) => {
+            config.leave_ignored_heads |= opt.no_ignored_disable;
+            opt.wait.wait(backend.as_ref())?;
+            let (heads, ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            let workspaces = if opt.preserve_workspaces {
+                Some(backend.get_workspaces()?)
+            } else {
                 None
+            };
+            let profile = match cli.profile {
+                None if config.restore_newest_profile => {
+                    let newest = newest_profile(&heads, &config)?;
+                    debug!(?newest, "Restoring newest profile");
+                    newest.unwrap_or(profile)
+                }
+                _ => profile,
+            };
+            let mut applied = None;
+            match load_head_config_with_fallbacks(&heads, &ignored_heads, &profile, &config) {
+                Ok((profile, mut saved)) => {
+                    if opt.prefer_current_mode {
+                        prefer_current_mode(&mut saved, &heads);
+                    }
+                    if let Some(factor) = opt.scale_factor {
+                        scale_heads(&mut saved, factor);
+                    }
+                    if opt.positions_only {
+                        keep_all_but_positions(&mut saved, &heads);
+                    }
+                    undoable(backend.as_ref(), || {
+                        apply_head_config(backend.as_ref(), &saved, &heads, &config)
+                    })?;
+                    run_hooks(&saved, &profile);
+                    applied = Some((profile, heads_hash(&heads, &config)));
+                }
+                Err(err) => {
+                    if opt.fallback_to_default {
+                        error!("{}", err);
+                        undoable(backend.as_ref(), || {
+                            apply_default(backend.as_ref(), &heads, &ignored_heads, &config)
+                        })?
+                    } else {
+                        Err(err)?;
+                    }
+                }
+            }
+
+            if let Some(workspaces) = workspaces {
+                restore_workspaces(backend.as_ref(), &workspaces)?;
+            }
+
+            if opt.interactive {
+                let previous_heads: Vec<Head> = heads.into_iter().chain(ignored_heads).collect();
+                if !confirm(std::time::Duration::from_secs(opt.timeout))? {
+                    say!("Reverting to previous configuration");
+                    backend.set_head_config(&previous_heads)?;
+                    applied = None;
+                }
+            }
+            if let Some((profile, hash)) = applied {
+                LastApplied::record(&profile, hash);
+            }
+        }
+        Commands::Info(opt) => {
+            let (mut heads, mut ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            let path = profile_path(&heads, &profile, &config)?;
+            if !opt.monitor.is_empty() {
+                let wanted = |h: &Head| opt.monitor.iter().any(|m| h.matches_query(m));
+                heads.retain(wanted);
+                ignored_heads.retain(wanted);
+                if heads.is_empty() && ignored_heads.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "no connected monitor matches {}",
+                        opt.monitor.join(", ")
+                    ));
+                }
+            }
+            if let Some(anonymize) = opt.anonymize.anonymize {
+                for head in heads.iter_mut().chain(ignored_heads.iter_mut()) {
+                    head.anonymize(anonymize);
+                }
+            }
+            let mut info = Info::new(&heads, &ignored_heads, path);
+            if opt.modes {
+                info.set_modes(backend.get_available_modes()?);
+            }
+
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
             } else {
-                Some(h)
+                print_info(&info);
             }
-        })
-        .collect();
-    heads.sort_by(Head::cmp_mms);
-
-    match cli.command {
-        Commands::Save => {
-            let base_directories = xdg::BaseDirectories::with_prefix("wlscsr")?;
-            let path = base_directories
-                .place_state_file(format!("{}.json", hex::encode(hash_heads(&heads))))?;
-            debug!("Saving screen config to {}", path.display());
-            heads.iter_mut().for_each(|h| {
-                h.name = None;
-            });
-            std::fs::write(path, serde_json::to_string_pretty(&heads)?)?;
-        }
-        Commands::Restore(ref opt) => match load_head_config(&heads, &ignored_heads) {
-            Ok(saved_heads) => backend.set_head_config(&saved_heads)?,
-            Err(err) => {
-                if opt.fallback_to_default {
-                    error!("{}", err);
-
-                    let active_head_names: Vec<String> =
-                        heads.iter().filter_map(|h| h.name.clone()).collect();
-                    let inactive_head_names: Vec<String> = ignored_heads
-                        .iter()
-                        .filter_map(|h| h.name.clone())
-                        .collect();
-                    backend.fallback_head_config(&active_head_names, &inactive_head_names)?
+        }
+        Commands::Apply(opt) => {
+            let (heads, ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            let saved = match_head_config(
+                read_profile_source(&opt.source, opt.format())?,
+                &heads,
+                &ignored_heads,
+                &config,
+                &opt.source,
+            )?;
+            undoable(backend.as_ref(), || {
+                apply_head_config(backend.as_ref(), &saved, &heads, &config)
+            })?;
+        }
+        Commands::ApplyForce(opt) => {
+            let (heads, ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            let saved = force_head_config(
+                read_profile_source(&opt.source, opt.format())?,
+                &heads,
+                &ignored_heads,
+                &config,
+            );
+            undoable(backend.as_ref(), || {
+                apply_head_config(backend.as_ref(), &saved, &heads, &config)
+            })?;
+        }
+        Commands::Serve => wlscsr::service::serve(backend)?,
+        Commands::Watch(opt) => {
+            opt.wait.wait(backend.as_ref())?;
+            watch(
+                backend.as_ref(),
+                &WatchOptions {
+                    profile: profile.clone(),
+                    interval: std::time::Duration::from_millis(opt.interval),
+                    settle: std::time::Duration::from_millis(opt.settle),
+                    once: opt.once,
+                    dry_run: opt.dry_run,
+                },
+            )?
+        }
+        Commands::Status(opt) => {
+            let status = match wlscsr::service::query_status(&profile) {
+                Ok(status) => status,
+                Err(err) => {
+                    debug!(%err, "D-Bus service not available");
+                    Status::new(backend.as_ref(), &config, &profile)?
+                }
+            };
+
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                print_status(&status);
+            }
+        }
+        Commands::Default => {
+            let (heads, ignored_heads) = current_heads(backend.as_ref(), &config)?;
+            undoable(backend.as_ref(), || {
+                apply_default(backend.as_ref(), &heads, &ignored_heads, &config)
+            })?;
+        }
+        Commands::Presentation(opt) if opt.off => {
+            undoable(backend.as_ref(), || {
+                presentation_off(backend.as_ref(), &profile, &config)
+            })?;
+        }
+        Commands::Presentation(_) => {
+            undoable(backend.as_ref(), || {
+                presentation_on(backend.as_ref(), &config)
+            })?;
+        }
+        Commands::Undo(opt) => undo(backend.as_ref(), opt.steps)?,
+        Commands::Rename(opt) => {
+            let heads = match opt.hash {
+                Some(_) => Vec::new(),
+                None => current_heads(backend.as_ref(), &config)?.0,
+            };
+            let path = rename_profile(
+                &heads,
+                opt.hash.as_deref(),
+                &opt.from,
+                &opt.to,
+                &config,
+                opt.force,
+            )?;
+            say!(
+                "Renamed profile {} to {} ({})",
+                opt.from,
+                opt.to,
+                path.display()
+            );
+        }
+        Commands::ReplaceSerial(opt) => {
+            let replacements = replace_serial(
+                &opt.make,
+                &opt.model,
+                &opt.old,
+                &opt.new,
+                &config,
+                opt.dry_run,
+            )?;
+            for replacement in &replacements {
+                let verb = if opt.dry_run {
+                    "Would rewrite"
+                } else {
+                    "Rewrote"
+                };
+                if replacement.to == replacement.from {
+                    say!("{} {}", verb, replacement.from.display());
                 } else {
-                    Err(err)?;
+                    say!(
+                        "{} {} as {}",
+                        verb,
+                        replacement.from.display(),
+                        replacement.to.display()
+                    );
                 }
             }
-        },
-        Commands::Info => {
-            let base_directories = xdg::BaseDirectories::with_prefix("wlscsr")?;
-            println!("{} connected heads:", heads.len() + ignored_heads.len());
-            for head in heads.iter() {
-                println!(
-                    "* {}\n  Make: {}\n  Model: {}\n  Serial: {}",
-                    head.name.as_deref().unwrap_or(""),
-                    &head.make,
-                    &head.model,
-                    &head.serial
-                );
+            if replacements.is_empty() {
+                say!("No saved profile has a monitor with serial {}", opt.old);
             }
-            for head in ignored_heads.iter() {
-                println!(
-                    "* {} [ignored]\n  Make: {}\n  Model: {}\n  Serial: {}",
-                    head.name.as_deref().unwrap_or(""),
-                    &head.make,
-                    &head.model,
-                    &head.serial
-                );
+        }
+        Commands::Profiles => {
+            let files = check_saved_profiles(&config)?;
+            for file in &files {
+                say!("{} {} {}", file.hash, file.profile, file.path.display());
+                for problem in &file.problems {
+                    say!("  problem: {}", problem);
+                }
             }
-            let path = base_directories
-                .get_state_file(format!("{}.json", hex::encode(hash_heads(&heads))));
 
-            println!("Configuration path: {}", path.display());
+            let problems = files.iter().filter(|f| !f.problems.is_empty()).count();
+            if problems > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} of {} saved profiles have problems",
+                    problems,
+                    files.len()
+                ));
+            }
+        }
+        Commands::Doctor
+        | Commands::ExportAll(_)
+        | Commands::ImportAll(_)
+        | Commands::Health(_)
+        | Commands::Log(_)
+        | Commands::Validate(_)
+        | Commands::Schema
+        | Commands::ListModes(_) => {
+            unreachable!()
+        }
+        Commands::Enable(opt) => enable_head(backend.as_ref(), &opt.head, &config)?,
+        Commands::Disable(opt) => disable_head(backend.as_ref(), &opt.head, &config)?,
+        Commands::Rotate(opt) => rotate_head(backend.as_ref(), &opt.head, opt.transform, &config)?,
+        Commands::Arrange(opt) => {
+            let (mut heads, _) = current_heads(backend.as_ref(), &config)?;
+            arrange_heads(&mut heads, opt.strategy);
+            if opt.dry_run {
+                for head in &heads {
+                    if let Some(ref head_config) = head.config {
+                        say!(
+                            "{}: {},{}",
+                            head.name.as_deref().unwrap_or_default(),
+                            head_config.x,
+                            head_config.y
+                        );
+                    }
+                }
+            } else {
+                undoable(backend.as_ref(), || backend.set_head_config(&heads))?;
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+fn print_info(info: &Info) {
+    say!("{} connected heads:", info.heads.len());
+    for head in info.heads.iter() {
+        say!(
+            "* {}{}\n  Make: {}\n  Model: {}\n  Serial: {}",
+            head.name,
+            if head.ignored { " [ignored]" } else { "" },
+            &head.make,
+            &head.model,
+            &head.serial
+        );
+        if let Some(ref modes) = head.modes {
+            say!("  Modes:");
+            for mode in modes {
+                let flags: Vec<&str> = [(mode.current, "current"), (mode.preferred, "preferred")]
+                    .into_iter()
+                    .filter_map(|(set, flag)| set.then_some(flag))
+                    .collect();
+                if flags.is_empty() {
+                    say!("    {}", mode.mode);
+                } else {
+                    say!("    {} ({})", mode.mode, flags.join(", "));
+                }
+            }
+        }
+    }
+
+    say!("Configuration path: {}", info.path.display());
+}
+
+/// Print the modes of the named head, or of all heads prefixed by their names
+fn list_modes(backend: &dyn Backend, name: Option<&str>) -> anyhow::Result<()> {
+    let mut modes: Vec<_> = backend.get_available_modes()?.into_iter().collect();
+    modes.sort_by(|a, b| a.0.cmp(&b.0));
+    if let Some(name) = name {
+        modes.retain(|(head, _)| head == name);
+        if modes.is_empty() {
+            return Err(anyhow::anyhow!("no head named {}", name));
         }
     }
 
+    for (head, head_modes) in modes {
+        for mode in head_modes {
+            let mut line = mode.mode.to_string();
+            if name.is_none() {
+                line = format!("{} {}", head, line);
+            }
+            for (set, flag) in [(mode.current, "current"), (mode.preferred, "preferred")] {
+                if set {
+                    line.push(' ');
+                    line.push_str(flag);
+                }
+            }
+            println!("{}", line);
+        }
+    }
     Ok(())
 }
 
-fn hash_heads(heads: &[Head]) -> [u8; 32] {
-    use sha2::{Digest, Sha256};
+/// Print the heads as reported by each backend side by side, one line per
+/// setting, marking settings that differ with `*`, followed by the hash each
+/// backend's heads would be saved under
+fn compare_backends(backends: &[(&str, Box<dyn Backend>)], config: &ConfigFile) {
+    let mut reports = Vec::new();
+    for (name, backend) in backends {
+        match backend.get_all_heads() {
+            Ok(heads) => reports.push((*name, backend.as_ref(), heads)),
+            Err(err) => say!("{}: {:#}", name, err),
+        }
+    }
 
-    let mut hasher = Sha256::new();
-    hasher.update(heads.len().to_le_bytes());
+    // Each head's settings as strings, keyed like `config.scale`
+    let fields = |head: &Head| {
+        let mut fields = std::collections::BTreeMap::new();
+        if let Ok(serde_json::Value::Object(object)) = serde_json::to_value(head) {
+            for (key, value) in object {
+                match value {
+                    serde_json::Value::Object(config) => {
+                        for (config_key, value) in config {
+                            fields.insert(format!("{}.{}", key, config_key), value.to_string());
+                        }
+                    }
+                    value => {
+                        fields.insert(key, value.to_string());
+                    }
+                }
+            }
+        }
+        fields
+    };
 
-    for head in heads {
-        for s in [&head.make, &head.model, &head.serial] {
-            let bytes = s.as_bytes();
-            hasher.update(bytes.len().to_le_bytes());
-            hasher.update(bytes);
+    let mut names: Vec<String> = reports
+        .iter()
+        .flat_map(|(_, _, heads)| heads.iter().filter_map(|h| h.name.clone()))
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        say!("{}", name);
+        let head_fields: Vec<_> = reports
+            .iter()
+            .map(|(_, _, heads)| {
+                heads
+                    .iter()
+                    .find(|h| h.name.as_ref() == Some(&name))
+                    .map(fields)
+                    .unwrap_or_default()
+            })
+            .collect();
+        let mut keys: Vec<&String> = head_fields.iter().flat_map(|f| f.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let values: Vec<&str> = head_fields
+                .iter()
+                .map(|f| f.get(key).map(String::as_str).unwrap_or("-"))
+                .collect();
+            let differs = values.iter().any(|v| *v != values[0]);
+            let columns: Vec<String> = reports
+                .iter()
+                .zip(&values)
+                .map(|((backend, _, _), value)| format!("{}={}", backend, value))
+                .collect();
+            say!(
+                "{} {:<24} {}",
+                if differs { "*" } else { " " },
+                key,
+                columns.join("  ")
+            );
         }
     }
 
-    hasher.finalize().into()
+    for (name, backend, _) in reports {
+        match current_heads(backend, config) {
+            Ok((heads, _)) => say!("Hash ({}): {}", name, heads_hash(&heads, config)),
+            Err(err) => say!("Hash ({}): {:#}", name, err),
+        }
+    }
 }
 
-fn load_head_config(heads: &[Head], ignored_heads: &[Head]) -> anyhow::Result<Vec<Head>> {
-    let base_directories = xdg::BaseDirectories::with_prefix("wlscsr")?;
-    let path = base_directories.get_state_file(format!("{}.json", hex::encode(hash_heads(heads))));
-    debug!("Attempting to load screen config from {}", path.display());
-    let mut saved_heads = serde_json::from_slice::<Vec<Head>>(&std::fs::read(&path)?)?;
-    if saved_heads.len() != heads.len() {
-        return Err(anyhow::anyhow!(
-            "Screen config {} does not match connected heads ({}!={})",
-            path.display(),
-            saved_heads.len(),
-            heads.len()
-        ));
+fn print_plan(plan: &Plan) {
+    say!("Profile: {}", plan.profile);
+    for head in plan.heads.iter() {
+        say!("{}: {}", head.name, head.action);
+        for change in head.changes.iter() {
+            say!("    {}: {} -> {}", change.field, change.from, change.to);
+        }
+    }
+    for warning in plan.warnings.iter() {
+        say!("Warning: {}", warning);
     }
-    saved_heads.sort_by(Head::cmp_mms);
+}
+
+fn print_explanation(explanation: &Explanation, heads: &[Head]) {
+    let describe = |head: Option<&Head>| {
+        head.map(|h| {
+            format!(
+                "{}{:?} {:?} {:?}",
+                h.name
+                    .as_ref()
+                    .map(|name| format!("{} ", name))
+                    .unwrap_or_default(),
+                h.make,
+                h.model,
+                h.serial
+            )
+        })
+        .unwrap_or_else(|| "-".to_string())
+    };
 
-    for (idx, (saved_head, head)) in saved_heads.iter_mut().zip(heads.iter()).enumerate() {
-        if (&saved_head.make, &saved_head.model, &saved_head.serial)
-            != (&head.make, &head.model, &head.serial)
-        {
-            return Err(anyhow::anyhow!(
-                "Screen config {} does not match connected heads (idx {})",
-                path.display(),
+    say!("Hash: {}", explanation.hash);
+    say!(
+        "Configuration path: {} ({})",
+        explanation.path.display(),
+        if explanation.exists {
+            "exists"
+        } else {
+            "missing"
+        }
+    );
+    if explanation.exists {
+        say!(
+            "Saved heads: {}, connected heads: {}{}",
+            explanation.saved_heads.len(),
+            heads.len(),
+            if explanation.saved_heads.len() != heads.len() {
+                " (count mismatch)"
+            } else {
+                ""
+            }
+        );
+        for idx in 0..explanation.saved_heads.len().max(heads.len()) {
+            let saved_head = explanation.saved_heads.get(idx);
+            let head = heads.get(idx);
+            let result = match (saved_head, head) {
+                (Some(saved_head), Some(head)) if saved_head.same_mms(head) => "matched",
+                (Some(_), Some(_)) => "identity mismatch",
+                (None, _) => "not in saved configuration",
+                (_, None) => "not connected",
+            };
+            say!(
+                "  #{}: saved {} / connected {}: {}",
                 idx,
-            ));
+                describe(saved_head),
+                describe(head),
+                result
+            );
         }
-        saved_head.name = head.name.clone();
     }
+    match explanation.result {
+        Ok(_) => say!("Result: saved configuration matches"),
+        Err(ref err) => say!("Result: {}", err),
+    }
+}
 
-    saved_heads.extend(ignored_heads.iter().map(|h| {
-        let mut h = h.clone();
-        h.config = None;
-        h
-    }));
-    debug!("Restoring config: {:?}", saved_heads);
-
-    Ok(saved_heads)
+/// Enable the heads in their preferred mode next to each other, and disable
+/// ignored heads
+fn apply_default(
+    backend: &dyn Backend,
+    heads: &[Head],
+    ignored_heads: &[Head],
+    config: &ConfigFile,
+) -> anyhow::Result<()> {
+    let active_head_names: Vec<String> = heads.iter().filter_map(|h| h.name.clone()).collect();
+    let inactive_head_names: Vec<String> = ignored_heads
+        .iter()
+        .filter(|_| !config.leave_ignored_heads)
+        .filter_map(|h| h.name.clone())
+        .collect();
+    backend.fallback_head_config(&active_head_names, &inactive_head_names)
 }
 
-fn read_config_file() -> anyhow::Result<ConfigFile> {
-    let base_directories = xdg::BaseDirectories::new()?;
-    let path = base_directories.get_config_file("wlscsr.toml");
+fn doctor(backend: &dyn Backend) -> anyhow::Result<()> {
+    let checks = diagnose(backend);
+    for check in &checks {
+        let status = match check.status {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        say!("[{}] {}", status, check.message);
+    }
+
+    let count = |status| checks.iter().filter(|c| c.status == status).count();
+    let failed = count(CheckStatus::Fail);
+    say!(
+        "{} passed, {} warnings, {} failed",
+        count(CheckStatus::Pass),
+        count(CheckStatus::Warn),
+        failed
+    );
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} checks failed", failed));
+    }
 
-    let contents = std::fs::read(path);
+    Ok(())
+}
 
-    if let Err(ref err) = contents {
-        if err.kind() == std::io::ErrorKind::NotFound {
-            return Ok(Default::default());
+fn print_status(status: &Status) {
+    say!(
+        "Daemon: {}",
+        if status.daemon {
+            "running"
+        } else {
+            "not running"
         }
+    );
+    say!(
+        "Profile: {} ({})",
+        status.profile,
+        if status.saved { "saved" } else { "not saved" }
+    );
+    say!("Configuration path: {}", status.path.display());
+    say!(
+        "Live layout matches saved configuration: {}",
+        if status.matches { "yes" } else { "no" }
+    );
+    say!(
+        "Active profiles: {}",
+        if status.active_profiles.is_empty() {
+            "none".to_string()
+        } else {
+            status.active_profiles.join(", ")
+        }
+    );
+    if let Some(ref last_applied) = status.last_applied {
+        say!(
+            "Last applied: {} at {}",
+            last_applied.profile,
+            humantime::format_rfc3339_seconds(
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs(last_applied.time)
+            )
+        );
+    }
+}
+
+fn print_health(health: &Health) {
+    let format_time = |time: u64| {
+        humantime::format_rfc3339_seconds(
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(time),
+        )
+    };
+    say!(
+        "Uptime: {}",
+        humantime::format_duration(std::time::Duration::from_secs(health.uptime))
+    );
+    match health.last_applied {
+        Some(ref last_applied) => say!(
+            "Last applied: {} at {}",
+            last_applied.profile,
+            format_time(last_applied.time)
+        ),
+        None => say!("Last applied: never"),
     }
+    match health.last_event {
+        Some(time) => say!("Last event: {}", format_time(time)),
+        None => say!("Last event: none"),
+    }
+    say!("Failed requests: {}", health.errors);
+}
+
+/// Wait for the user to press Enter, showing a countdown. Returns whether the
+/// user confirmed before the timeout.
+fn confirm(timeout: std::time::Duration) -> anyhow::Result<bool> {
+    use std::io::Write;
 
-    Ok(toml::from_str(std::str::from_utf8(&contents?)?)?)
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        if matches!(std::io::stdin().read_line(&mut line), Ok(n) if n > 0) {
+            let _ = sender.send(());
+        }
+    });
+
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            println!();
+            return Ok(false);
+        }
+
+        print!(
+            "\rKeep this configuration? Press Enter to confirm, reverting in {}s ",
+            remaining.as_secs_f64().ceil()
+        );
+        std::io::stdout().flush()?;
+
+        match receiver.recv_timeout(remaining.min(std::time::Duration::from_secs(1))) {
+            Ok(()) => return Ok(true),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                println!();
+                return Ok(false);
+            }
+        }
+    }
 }
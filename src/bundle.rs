@@ -0,0 +1,230 @@
+use std::io::Read;
+use std::path::Path;
+
+use tracing::{debug, info, warn};
+
+use crate::config::{ConfigFile, read_config_file};
+use crate::dirs;
+use crate::format::Format;
+use crate::profile::{
+    hashed_profile_file_name, list_saved_profile_files, parse_profile_file_name, saved_heads_hash,
+};
+use crate::types::{Anonymize, SavedConfig};
+
+/// Name of the config file, both in the XDG config directory and in bundles
+const CONFIG_FILE_NAME: &str = "wlscsr.toml";
+/// Directory saved profiles are stored in within bundles
+const PROFILES_DIR: &str = "profiles";
+
+/// Write all saved profiles and the config file into a `.tar.gz` bundle at
+/// `path`. Returns the number of profiles written.
+///
+/// Saved profiles are looked for in the same directories as when restoring,
+/// and of those with the same file name, only the one that would be restored
+/// is written.
+///
+/// With `anonymize`, serials (and makes and models) of the heads in saved and
+/// declared profiles are replaced with placeholders, and saved profiles are
+/// renamed after the hash of the anonymized heads, so that they are still
+/// found when the bundle is imported.
+///
+/// Profiles are written in the given format, with the file extension changed
+/// accordingly.
+pub fn export_all(
+    path: &Path,
+    anonymize: Option<Anonymize>,
+    format: Format,
+) -> anyhow::Result<usize> {
+    let file = std::fs::File::create(path)?;
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        file,
+        flate2::Compression::default(),
+    ));
+
+    // Without `anonymize`, the config only gives `profile_dir`, so a broken
+    // config file does not keep the state directory from being backed up
+    let config = match read_config_file() {
+        Ok(config) => config,
+        Err(err) if anonymize.is_none() => {
+            warn!(%err, "Failed to read config file, not exporting profiles in profile_dir");
+            ConfigFile::default()
+        }
+        Err(err) => return Err(err),
+    };
+    let config_path = dirs::config_file(CONFIG_FILE_NAME)?;
+    if config_path.exists() {
+        debug!(path = %config_path.display(), "Exporting config file");
+        match anonymize {
+            Some(anonymize) => {
+                let mut config = read_config_file()?;
+                for head in config.profiles.iter_mut().flat_map(|p| p.heads.iter_mut()) {
+                    head.anonymize(anonymize);
+                }
+                append_file(&mut builder, CONFIG_FILE_NAME, toml::to_string(&config)?)?;
+            }
+            None => builder.append_path_with_name(&config_path, CONFIG_FILE_NAME)?,
+        }
+    }
+
+    let mut count = 0;
+    for profile_path in list_saved_profile_files(&config)? {
+        let Some((hash, profile)) = parse_profile_file_name(&profile_path) else {
+            continue;
+        };
+        let Some(name) = profile_path.file_name() else {
+            continue;
+        };
+        debug!(path = %profile_path.display(), "Exporting profile");
+        match anonymize {
+            Some(anonymize) => {
+                let mut saved = SavedConfig::from_slice(&std::fs::read(&profile_path)?)?;
+                let default_idx = saved
+                    .default_head
+                    .as_deref()
+                    .and_then(|reference| saved.heads.iter().position(|h| h.is_wanted(reference)));
+                for head in saved.heads.iter_mut() {
+                    head.anonymize(anonymize);
+                }
+                saved.default_head =
+                    default_idx.and_then(|idx| saved.heads[idx].identity_reference());
+                // Without a hash to recompute, the file keeps its name
+                let name = match saved_heads_hash(saved.heads.clone(), &hash, &config) {
+                    Some(hash) => hashed_profile_file_name(&hash, &profile)?,
+                    None => name.to_string_lossy().into_owned(),
+                };
+                append_file(
+                    &mut builder,
+                    Path::new(PROFILES_DIR)
+                        .join(name)
+                        .with_extension(format.extension()),
+                    format.write(&saved)?,
+                )?;
+            }
+            _ if format != Format::Json => {
+                let saved = SavedConfig::from_slice(&std::fs::read(&profile_path)?)?;
+                append_file(
+                    &mut builder,
+                    Path::new(PROFILES_DIR)
+                        .join(name)
+                        .with_extension(format.extension()),
+                    format.write(&saved)?,
+                )?;
+            }
+            _ => {
+                builder.append_path_with_name(&profile_path, Path::new(PROFILES_DIR).join(name))?
+            }
+        }
+        count += 1;
+    }
+
+    builder.into_inner()?.finish()?;
+    Ok(count)
+}
+
+/// Add a file with the given contents to the bundle
+fn append_file<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    name: impl AsRef<Path>,
+    contents: String,
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+    );
+    header.set_cksum();
+    builder.append_data(&mut header, name, contents.as_bytes())?;
+    Ok(())
+}
+
+/// Restore saved profiles and the config file from a bundle written by
+/// `export_all`. Returns the number of profiles imported. Profiles in YAML
+/// are converted to JSON.
+///
+/// Existing files with different contents are only replaced if `overwrite`
+/// is set. Profiles whose file name does not match the hash of the heads saved
+/// in them, e.g. because the config differs in `identity_normalization`, are
+/// imported with a warning.
+pub fn import_all(path: &Path, overwrite: bool) -> anyhow::Result<usize> {
+    let file = std::fs::File::open(path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+
+    let mut config_contents = None;
+    let mut profiles = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+
+        if entry_path == Path::new(CONFIG_FILE_NAME) {
+            config_contents = Some(contents);
+        } else if entry_path.parent() == Some(Path::new(PROFILES_DIR))
+            && let format = Format::from_path(&entry_path)
+            && let json_path = entry_path.with_extension("json")
+            && let Some((hash, _)) = parse_profile_file_name(&json_path)
+            && let Some(name) = json_path.file_name().and_then(|name| name.to_str())
+        {
+            profiles.push((name.to_string(), hash, format, contents));
+        } else {
+            warn!(path = %entry_path.display(), "Ignoring unexpected file in bundle");
+        }
+    }
+
+    // The config is imported first, since it determines the hashes
+    if let Some(contents) = config_contents {
+        write_file(&dirs::config_file(CONFIG_FILE_NAME)?, &contents, overwrite)?;
+    }
+    let config = read_config_file()?;
+
+    let mut count = 0;
+    for (name, hash, format, contents) in profiles {
+        let saved = format
+            .parse(&contents)
+            .map_err(|err| err.context(format!("invalid profile {}", name)))?;
+        let contents = match format {
+            Format::Json => contents,
+            Format::Yaml => serde_json::to_string_pretty(&saved)?.into_bytes(),
+        };
+        if let Some(computed_hash) = saved_heads_hash(saved.heads, &hash, &config)
+            && computed_hash != hash
+        {
+            warn!(
+                profile = name,
+                computed_hash, "Heads in profile hash differently, it will not be found"
+            );
+        }
+
+        if write_file(&dirs::place_state_file(&name)?, &contents, overwrite)? {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Write `contents` to `path`, unless a file with different contents exists
+/// there and `overwrite` is not set. Returns whether the file was written.
+fn write_file(path: &Path, contents: &[u8], overwrite: bool) -> anyhow::Result<bool> {
+    match std::fs::read(path) {
+        Ok(existing) if existing == contents => {
+            debug!(path = %path.display(), "File is up to date");
+            return Ok(true);
+        }
+        Ok(_) if !overwrite => {
+            warn!(path = %path.display(), "File exists with different contents, skipping it");
+            return Ok(false);
+        }
+        _ => (),
+    }
+
+    info!(path = %path.display(), "Importing file");
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(true)
+}
@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{AvailableMode, Head, HeadConfig};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub lid: Vec<LidConfig>,
+    /// Include connector names in the hash identifying a set of heads
+    #[serde(default)]
+    pub hash_connector_names: bool,
+    /// Profiles to try in order when restoring, if the requested profile has
+    /// not been saved for the connected heads
+    #[serde(default)]
+    pub fallback_profiles: Vec<String>,
+    /// How make, model and serial are normalized before heads are hashed and
+    /// matched
+    #[serde(default)]
+    pub identity_normalization: IdentityNormalization,
+    /// Identify heads with empty make, model and serial (e.g. headless
+    /// outputs) by their connector name
+    #[serde(default)]
+    pub connector_name_fallback: bool,
+    /// Enable or disable VRR on all heads while on AC power, regardless of
+    /// the saved config
+    #[serde(default)]
+    pub vrr_on_ac: Option<bool>,
+    /// Enable or disable VRR on all heads while on battery, regardless of
+    /// the saved config
+    #[serde(default)]
+    pub vrr_on_battery: Option<bool>,
+    /// Canonical names for makes and models reported differently by
+    /// different backends or versions, applied before normalization
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// Also apply `BUILTIN_ALIASES`, after `aliases`
+    #[serde(default)]
+    pub builtin_aliases: bool,
+    /// Never change the monitors, as with `--read-only`
+    #[serde(default)]
+    pub read_only: bool,
+    /// How scales are adjusted before they are passed to the backend, if not
+    /// the backend's default: `NearestValid` for hyprctl, which used to get
+    /// them `Exact`ly, and `Exact` for wlr-randr
+    #[serde(default)]
+    pub scale_policy: Option<ScalePolicy>,
+    /// Which mode is taken to be the current one of an enabled head if the
+    /// backend does not say
+    #[serde(default)]
+    pub mode_policy: ModePolicy,
+    /// Configure heads with `monitorv2` blocks when using hyprctl, as with
+    /// `--monitorv2`
+    #[serde(default)]
+    pub monitorv2: bool,
+    /// How far to fall back if a saved mode is not available
+    #[serde(default)]
+    pub mode_degradation: ModeDegradation,
+    /// How to match saved heads to connected heads that cannot be told apart
+    #[serde(default)]
+    pub ambiguous_heads: AmbiguousHeads,
+    /// Profiles declared here rather than saved with `save`, which take
+    /// precedence over saved ones of the same name
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<DeclaredProfile>,
+    /// How the hash identifying a set of heads is written in file names
+    #[serde(default)]
+    pub hash: HashAlgorithm,
+    /// How the daemon (`serve` or `watch`) waits for consistent queries of
+    /// the heads
+    #[serde(default)]
+    pub stable_reads: StableReads,
+    /// Save configs as compact JSON rather than pretty-printed
+    #[serde(default)]
+    pub compact_json: bool,
+    /// Save heads that are where the compositor would place them
+    /// automatically with `auto_position`, rather than at exact coordinates
+    #[serde(default)]
+    pub auto_positions: bool,
+    /// Workspaces bound to heads after restoring
+    #[serde(default)]
+    pub workspace_map: Vec<WorkspaceMap>,
+    /// Restore the newest profile of the connected heads if none is given,
+    /// rather than the default one
+    #[serde(default)]
+    pub restore_newest_profile: bool,
+    /// Leave heads ignored because of a closed lid alone when restoring,
+    /// instead of disabling them
+    #[serde(default)]
+    pub leave_ignored_heads: bool,
+    /// Directory searched for saved configs before the state directory
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_dir: Option<PathBuf>,
+}
+
+/// Repeated queries of the heads until two consecutive ones agree
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct StableReads {
+    /// Milliseconds between queries
+    pub interval_ms: u64,
+    /// Milliseconds after which the last query is used, even if it differs
+    /// from the one before; 0 turns repeated queries off
+    pub timeout_ms: u64,
+}
+
+impl Default for StableReads {
+    fn default() -> Self {
+        Self {
+            interval_ms: 200,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+/// A profile declared in the config file, as a `[[profile]]` table
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeclaredProfile {
+    pub name: String,
+    pub heads: Vec<Head>,
+    /// Shell commands run after the config has been applied
+    #[serde(default)]
+    pub hooks: Vec<String>,
+    /// Shell command deciding whether the profile may be used: only if it
+    /// exits successfully
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+}
+
+/// PNP IDs of common manufacturers, as reported as make when the backend
+/// does not look them up, with the names they are looked up as
+pub const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("ACR", "Acer Technologies"),
+    ("AUS", "ASUSTek COMPUTER INC"),
+    ("BNQ", "BenQ Corporation"),
+    ("DEL", "Dell Inc."),
+    ("ENC", "Eizo Nanao Corporation"),
+    ("GSM", "LG Electronics"),
+    ("HWP", "Hewlett Packard"),
+    ("LEN", "Lenovo Group Limited"),
+    ("PHL", "Philips Consumer Electronics Company"),
+    ("SAM", "Samsung Electric Company"),
+    ("SNY", "Sony"),
+];
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdentityNormalization {
+    /// Use make, model and serial as reported by the backend
+    #[default]
+    None,
+    /// Strip leading and trailing white space
+    Trim,
+    /// Strip leading and trailing white space and convert to lowercase
+    TrimLowercase,
+}
+
+impl IdentityNormalization {
+    pub fn normalize(&self, s: &str) -> String {
+        match self {
+            IdentityNormalization::None => s.to_string(),
+            IdentityNormalization::Trim => s.trim().to_string(),
+            IdentityNormalization::TrimLowercase => s.trim().to_lowercase(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScalePolicy {
+    /// Pass scales on as they are
+    Exact,
+    /// Round scales to multiples of 1/120, as used by the fractional scale
+    /// protocol
+    #[serde(rename = "round-to-120ths")]
+    RoundTo120ths,
+    /// Round scales to the nearest multiple of 1/120 that divides the width
+    /// and height of the mode evenly, as Hyprland requires
+    NearestValid,
+}
+
+impl ScalePolicy {
+    /// The scale to pass to the backend for the given head config
+    pub fn apply(&self, config: &HeadConfig) -> f64 {
+        let in_120ths = (config.scale * 120.0).round();
+        match self {
+            ScalePolicy::Exact => config.scale,
+            ScalePolicy::RoundTo120ths => in_120ths / 120.0,
+            ScalePolicy::NearestValid => {
+                let is_valid = |scale: f64| {
+                    [config.width, config.height].iter().all(|&size| {
+                        let logical = size as f64 / scale;
+                        (logical - logical.round()).abs() < 0.01
+                    })
+                };
+                // Search outwards from the given scale, up to 1 either way
+                (0..=120)
+                    .flat_map(|offset| [in_120ths - offset as f64, in_120ths + offset as f64])
+                    .filter(|&n| n > 0.0)
+                    .map(|n| n / 120.0)
+                    .find(|&scale| is_valid(scale))
+                    .unwrap_or(in_120ths / 120.0)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModePolicy {
+    /// The first mode listed
+    #[default]
+    First,
+    /// The mode with the most pixels, then the highest refresh rate
+    HighestRes,
+    /// The mode with the highest refresh rate, then the most pixels
+    HighestRefresh,
+}
+
+impl ModePolicy {
+    /// Pick one of `modes`, if there are any
+    pub fn pick<'a>(&self, modes: &'a [AvailableMode]) -> Option<&'a AvailableMode> {
+        let pixels = |m: &AvailableMode| m.mode.width as i64 * m.mode.height as i64;
+        match self {
+            ModePolicy::First => modes.first(),
+            ModePolicy::HighestRes => modes.iter().max_by(|a, b| {
+                pixels(a)
+                    .cmp(&pixels(b))
+                    .then(a.mode.refresh_rate.total_cmp(&b.mode.refresh_rate))
+            }),
+            ModePolicy::HighestRefresh => modes.iter().max_by(|a, b| {
+                a.mode
+                    .refresh_rate
+                    .total_cmp(&b.mode.refresh_rate)
+                    .then(pixels(a).cmp(&pixels(b)))
+            }),
+        }
+    }
+}
+
+/// Steps of falling back to other modes if a saved mode is not available,
+/// each including the ones before it
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModeDegradation {
+    /// Fail, or leave it to the backend for heads that are already enabled
+    #[default]
+    None,
+    /// The same resolution with a lower refresh rate
+    SameResolution,
+    /// A lower resolution with the same aspect ratio
+    SameAspect,
+    /// The preferred mode
+    Preferred,
+}
+
+/// How the SHA-256 hash of a set of heads is written in the names of saved
+/// config files
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    /// All of it, as 64 hex digits
+    #[default]
+    Sha256,
+    /// The first 64 bits, as 16 hex digits. This stands in for a shorter
+    /// hash function such as BLAKE3 or xxHash: the file names are shorter,
+    /// but the full SHA-256 hash is still computed, so it is no faster.
+    Short,
+}
+
+impl HashAlgorithm {
+    /// Number of hex digits of hashes
+    pub fn hex_digits(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 64,
+            HashAlgorithm::Short => 16,
+        }
+    }
+
+    /// The algorithm hashes with the given number of hex digits are written
+    /// with
+    pub fn from_hex_digits(digits: usize) -> Option<Self> {
+        [HashAlgorithm::Sha256, HashAlgorithm::Short]
+            .into_iter()
+            .find(|algorithm| algorithm.hex_digits() == digits)
+    }
+
+    pub fn encode(&self, digest: &[u8; 32]) -> String {
+        hex::encode(&digest[..self.hex_digits() / 2])
+    }
+}
+
+/// How to match heads with the same make, model and serial, if their saved
+/// configs differ
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AmbiguousHeads {
+    /// Fail to restore
+    #[default]
+    Error,
+    /// Match them in the order the backend lists the connected heads
+    Order,
+    /// Match each connected head to the saved head closest to its current
+    /// position
+    Geometry,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LidConfig {
+    /// Where to read the lid state from
+    #[serde(default)]
+    pub source: LidSource,
+    /// ACPI lid state file, for the `file` source
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// DRM connector name (e.g. `eDP-1`) whose status is read, for the `drm`
+    /// source
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connector: Option<String>,
+    pub head: HeadPattern,
+}
+
+/// Directory listing the DRM connectors as `card<N>-<connector>`
+const DRM_CLASS_DIR: &str = "/sys/class/drm";
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LidSource {
+    /// An ACPI lid state file, which ends with `closed` if the lid is closed
+    #[default]
+    File,
+    /// The sysfs status of a DRM connector, which is `disconnected` if the
+    /// lid is closed
+    Drm,
+}
+
+impl LidConfig {
+    /// The file the lid state is read from
+    pub fn state_file(&self) -> anyhow::Result<PathBuf> {
+        match self.source {
+            LidSource::File => self
+                .file
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("lid with file source has no file")),
+            LidSource::Drm => {
+                let connector = self
+                    .connector
+                    .as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("lid with drm source has no connector"))?;
+                std::fs::read_dir(DRM_CLASS_DIR)
+                    .map_err(|err| {
+                        anyhow::Error::new(err).context(format!("failed to list {}", DRM_CLASS_DIR))
+                    })?
+                    .filter_map(|entry| entry.ok())
+                    .find(|entry| {
+                        entry.file_name().to_str().is_some_and(|name| {
+                            name.strip_prefix("card")
+                                .and_then(|name| name.split_once('-'))
+                                .is_some_and(|(card, name)| {
+                                    card.chars().all(|c| c.is_ascii_digit()) && name == connector
+                                })
+                        })
+                    })
+                    .map(|entry| entry.path().join("status"))
+                    .ok_or_else(|| anyhow::anyhow!("no DRM connector named {}", connector))
+            }
+        }
+    }
+
+    /// Whether the lid is closed. Errors count as open.
+    pub fn is_closed(&self) -> bool {
+        let Some(contents) = self
+            .state_file()
+            .and_then(|path| Ok(std::fs::read(path)?))
+            .ok()
+        else {
+            return false;
+        };
+        match self.source {
+            LidSource::File => contents.trim_ascii().ends_with(b"closed"),
+            LidSource::Drm => contents.trim_ascii() == b"disconnected",
+        }
+    }
+}
+
+/// Workspaces to be shown on a head
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkspaceMap {
+    /// Connector name, serial number, or make, model and serial separated by
+    /// spaces
+    pub head: String,
+    pub workspaces: Vec<u32>,
+}
+
+/// A connector name, or a regular expression matching connector names,
+/// compiled when the config file is read
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct HeadPattern {
+    pattern: String,
+    regex: regex::Regex,
+}
+
+impl HeadPattern {
+    /// Whether the pattern is the given connector name, or a regular
+    /// expression matching all of it
+    pub fn matches(&self, name: &str) -> bool {
+        self.pattern == name || self.regex.is_match(name)
+    }
+}
+
+impl PartialEq for HeadPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl std::str::FromStr for HeadPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regex = regex::Regex::new(&format!("^(?:{})$", s))
+            .map_err(|err| format!("invalid head pattern {:?}: {}", s, err))?;
+        Ok(Self {
+            pattern: s.to_string(),
+            regex,
+        })
+    }
+}
+
+impl TryFrom<String> for HeadPattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<HeadPattern> for String {
+    fn from(pattern: HeadPattern) -> Self {
+        pattern.pattern
+    }
+}
+
+impl std::fmt::Display for HeadPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl ConfigFile {
+    /// The canonical name of a make or model, according to `aliases` and, if
+    /// enabled, `BUILTIN_ALIASES`
+    pub fn alias<'a>(&'a self, s: &'a str) -> &'a str {
+        if let Some(alias) = self.aliases.get(s) {
+            return alias;
+        }
+        if self.builtin_aliases
+            && let Some((_, alias)) = BUILTIN_ALIASES.iter().find(|(raw, _)| *raw == s)
+        {
+            return alias;
+        }
+        s
+    }
+
+    /// Check that no workspace is mapped to more than one head
+    fn check_workspace_map(&self) -> anyhow::Result<()> {
+        let mut heads: HashMap<u32, &str> = HashMap::new();
+        for map in &self.workspace_map {
+            for &workspace in &map.workspaces {
+                if let Some(head) = heads.insert(workspace, &map.head)
+                    && head != map.head
+                {
+                    return Err(anyhow::anyhow!(
+                        "workspace {} is mapped to both {} and {}",
+                        workspace,
+                        head,
+                        map.head
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Patterns of heads that should be ignored because of a closed lid
+    pub fn ignored_head_patterns(&self) -> Vec<&HeadPattern> {
+        self.lid
+            .iter()
+            .filter(|lid| lid.is_closed())
+            .map(|lid| &lid.head)
+            .collect()
+    }
+}
+
+/// System-wide config file, read before the user's
+pub const SYSTEM_CONFIG_FILE: &str = "/etc/wlscsr.toml";
+
+/// The config files merged by `read_config_file`, from lowest to highest
+/// precedence: the system-wide one (unless in portable mode), the user's and
+/// the one given on the command line, if any
+pub fn config_files() -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    if !crate::dirs::is_portable()? {
+        paths.push(PathBuf::from(SYSTEM_CONFIG_FILE));
+    }
+    paths.push(crate::dirs::config_file("wlscsr.toml")?);
+    paths.extend(crate::dirs::extra_config_file());
+    Ok(paths)
+}
+
+/// Read and merge the `config_files` (see `merge_config`). Files that do not
+/// exist are skipped, except for the one given on the command line.
+pub fn read_config_file() -> anyhow::Result<ConfigFile> {
+    let extra_config_file = crate::dirs::extra_config_file();
+    let mut merged = toml::Table::new();
+
+    for path in config_files()? {
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err)
+                if err.kind() == std::io::ErrorKind::NotFound
+                    && extra_config_file.as_ref() != Some(&path) =>
+            {
+                continue;
+            }
+            Err(err) => {
+                return Err(
+                    anyhow::Error::new(err).context(format!("failed to read {}", path.display()))
+                );
+            }
+        };
+        let table: toml::Table =
+            toml::from_str(std::str::from_utf8(&contents)?).map_err(|err| {
+                anyhow::Error::new(err).context(format!("invalid {}", path.display()))
+            })?;
+        merge_config(&mut merged, table);
+    }
+
+    let config: ConfigFile = toml::Value::Table(merged).try_into()?;
+    config.check_workspace_map()?;
+    Ok(config)
+}
+
+/// Merge the config `overlay` into `base`: tables (like `[aliases]`) are
+/// merged key by key, lists of tables (like `[[lid]]`) are concatenated, and
+/// any other setting, including lists of strings, is replaced
+fn merge_config(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_config(base_table, table)
+            }
+            (Some(toml::Value::Array(base_array)), toml::Value::Array(array))
+                if base_array.iter().chain(&array).all(toml::Value::is_table) =>
+            {
+                base_array.extend(array)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Transform;
+    use crate::types::tests::head_config;
+
+    #[test]
+    fn head_pattern_matches_regex() {
+        let pattern: HeadPattern = "eDP-.*".parse().unwrap();
+        assert!(pattern.matches("eDP-1"));
+        assert!(pattern.matches("eDP-2"));
+        assert!(!pattern.matches("DP-1"));
+        assert!(!pattern.matches("xeDP-1"));
+    }
+
+    #[test]
+    fn head_pattern_matches_name() {
+        let pattern: HeadPattern = "HDMI-A-1".parse().unwrap();
+        assert!(pattern.matches("HDMI-A-1"));
+        assert!(!pattern.matches("HDMI-A-10"));
+    }
+
+    fn scaled(policy: ScalePolicy, width: i32, height: i32, scale: f64) -> f64 {
+        policy.apply(&HeadConfig {
+            width,
+            height,
+            ..head_config(Transform::Normal, scale)
+        })
+    }
+
+    #[test]
+    fn scale_policy_exact() {
+        assert_eq!(scaled(ScalePolicy::Exact, 2256, 1504, 1.33), 1.33);
+    }
+
+    #[test]
+    fn scale_policy_round_to_120ths() {
+        assert_eq!(
+            scaled(ScalePolicy::RoundTo120ths, 2256, 1504, 1.33),
+            160.0 / 120.0
+        );
+        assert_eq!(scaled(ScalePolicy::RoundTo120ths, 2256, 1504, 1.5), 1.5);
+    }
+
+    #[test]
+    fn scale_policy_repeating_fraction() {
+        // 4/3 as typed in by hand, or as saved by an older version
+        assert_eq!(scaled(ScalePolicy::Exact, 2256, 1504, 1.333333), 1.333333);
+        assert_eq!(
+            scaled(ScalePolicy::RoundTo120ths, 2256, 1504, 1.333333),
+            160.0 / 120.0
+        );
+        assert_eq!(
+            scaled(ScalePolicy::NearestValid, 2256, 1504, 1.333333),
+            160.0 / 120.0
+        );
+        // 1.33 is 159.6/120, which rounds to the same
+        assert_eq!(scaled(ScalePolicy::Exact, 2256, 1504, 1.33), 1.33);
+        assert_eq!(
+            scaled(ScalePolicy::RoundTo120ths, 2256, 1504, 1.33),
+            scaled(ScalePolicy::RoundTo120ths, 2256, 1504, 1.333333)
+        );
+    }
+
+    #[test]
+    fn scale_policy_nearest_valid() {
+        // 1504 / 1.5 is not a whole number of pixels, 1504 / (188 / 120) is
+        assert_eq!(
+            scaled(ScalePolicy::NearestValid, 2256, 1504, 1.5),
+            188.0 / 120.0
+        );
+        assert_eq!(scaled(ScalePolicy::NearestValid, 3840, 2160, 1.5), 1.5);
+        assert_eq!(scaled(ScalePolicy::NearestValid, 1920, 1080, 1.0), 1.0);
+    }
+
+    fn merged(base: &str, overlay: &str) -> ConfigFile {
+        let mut table: toml::Table = toml::from_str(base).unwrap();
+        merge_config(&mut table, toml::from_str(overlay).unwrap());
+        toml::Value::Table(table).try_into().unwrap()
+    }
+
+    #[test]
+    fn merge_config_concatenates_lids() {
+        let config = merged(
+            r#"
+            [[lid]]
+            file = "/proc/acpi/button/lid/LID/state"
+            head = "eDP-1"
+            "#,
+            r#"
+            [[lid]]
+            source = "drm"
+            connector = "eDP-2"
+            head = "eDP-2"
+            "#,
+        );
+        let heads: Vec<String> = config.lid.iter().map(|l| l.head.to_string()).collect();
+        assert_eq!(heads, ["eDP-1", "eDP-2"]);
+        assert_eq!(config.lid[1].source, LidSource::Drm);
+    }
+
+    #[test]
+    fn merge_config_replaces_fallback_profiles() {
+        let config = merged(
+            r#"
+            fallback_profiles = ["work", "home"]
+            builtin_aliases = true
+            [aliases]
+            DEL = "Dell"
+            "#,
+            r#"
+            fallback_profiles = ["travel"]
+            [aliases]
+            GSM = "LG"
+            "#,
+        );
+        assert_eq!(config.fallback_profiles, ["travel"]);
+        assert!(config.builtin_aliases);
+        assert_eq!(config.aliases.len(), 2);
+    }
+
+    #[test]
+    fn invalid_head_pattern_is_config_error() {
+        let result: Result<ConfigFile, _> = toml::from_str(
+            r#"
+            [[lid]]
+            file = "/proc/acpi/button/lid/LID0/state"
+            head = "eDP-("
+            "#,
+        );
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("invalid head pattern"), "{}", err);
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Name of the file next to the executable that turns on portable mode
+pub const PORTABLE_MARKER: &str = "wlscsr.portable";
+
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+static PROFILE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+static EXTRA_CONFIG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Keep config and state next to the executable instead of in the XDG base
+/// directories
+pub fn set_portable(portable: bool) {
+    PORTABLE.store(portable, Ordering::Relaxed);
+}
+
+/// Look for saved screen configs in `dir` before the state directory, and
+/// save them there
+pub fn set_profile_dir(dir: Option<PathBuf>) {
+    *PROFILE_DIR.write().unwrap() = dir;
+}
+
+/// The directory given with `set_profile_dir`, if any
+pub fn profile_dir() -> Option<PathBuf> {
+    PROFILE_DIR.read().unwrap().clone()
+}
+
+/// Read the config file at `path` after all others, so that its settings take
+/// precedence
+pub fn set_extra_config_file(path: Option<PathBuf>) {
+    *EXTRA_CONFIG_FILE.write().unwrap() = path;
+}
+
+/// The config file given with `set_extra_config_file`, if any
+pub fn extra_config_file() -> Option<PathBuf> {
+    EXTRA_CONFIG_FILE.read().unwrap().clone()
+}
+
+/// Whether config and state are kept next to the executable
+pub fn is_portable() -> anyhow::Result<bool> {
+    Ok(portable_dir()?.is_some())
+}
+
+/// The directory containing the executable, if running in portable mode
+///
+/// Portable mode is on if requested with `set_portable`, or if a
+/// `wlscsr.portable` file exists next to the executable.
+fn portable_dir() -> anyhow::Result<Option<PathBuf>> {
+    let exe = std::env::current_exe()?;
+    let Some(dir) = exe.parent() else {
+        return Ok(None);
+    };
+
+    Ok(
+        (PORTABLE.load(Ordering::Relaxed) || dir.join(PORTABLE_MARKER).exists())
+            .then(|| dir.to_path_buf()),
+    )
+}
+
+/// Path of the config file with the given name
+pub fn config_file(name: &str) -> anyhow::Result<PathBuf> {
+    Ok(match portable_dir()? {
+        Some(dir) => dir.join(name),
+        None => xdg::BaseDirectories::new()?.get_config_file(name),
+    })
+}
+
+/// Directory where saved screen configs and other state are kept
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    Ok(match portable_dir()? {
+        Some(dir) => dir.join("wlscsr-state"),
+        None => xdg::BaseDirectories::with_prefix("wlscsr")?.get_state_home(),
+    })
+}
+
+/// Path of the state file with the given (relative) name
+pub fn state_file(name: &str) -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join(name))
+}
+
+/// Like `state_file`, but creates the directories leading to it
+pub fn place_state_file(name: &str) -> anyhow::Result<PathBuf> {
+    let path = state_file(name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(path)
+}
+
+/// Paths of all files in the state directory
+pub fn list_state_files() -> anyhow::Result<Vec<PathBuf>> {
+    list_files(&state_dir()?)
+}
+
+/// Paths of all files in `dir`, which need not exist
+pub fn list_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => Ok(entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file())
+            .collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
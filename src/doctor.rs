@@ -0,0 +1,148 @@
+use crate::backend::Backend;
+use crate::config::{config_files, read_config_file};
+use crate::dirs;
+use crate::profile::{check_saved_profiles, current_heads};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of one of the checks done by `diagnose`
+pub struct Check {
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Check {
+    fn new(status: CheckStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Check the environment for problems that keep wlscsr from working: the
+/// backend, the config file, lid state files, head identities and saved
+/// profiles
+pub fn diagnose(backend: &dyn Backend) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let config_path = config_files()
+        .map(|paths| {
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_else(|err| format!("<{:#}>", err));
+    let config = match read_config_file() {
+        Ok(config) => {
+            checks.push(Check::new(
+                CheckStatus::Pass,
+                format!("Config files {} are valid (or absent)", config_path),
+            ));
+            config
+        }
+        Err(err) => {
+            checks.push(Check::new(
+                CheckStatus::Fail,
+                format!("Config files {} are invalid: {:#}", config_path, err),
+            ));
+            Default::default()
+        }
+    };
+
+    for lid in &config.lid {
+        checks.push(match lid.state_file() {
+            Ok(file) => match std::fs::read(&file) {
+                Ok(_) => Check::new(
+                    CheckStatus::Pass,
+                    format!("Lid state file {} is readable", file.display()),
+                ),
+                Err(err) => Check::new(
+                    CheckStatus::Fail,
+                    format!("Lid state file {} is unreadable: {}", file.display(), err),
+                ),
+            },
+            Err(err) => Check::new(
+                CheckStatus::Fail,
+                format!("Lid state file for {} not found: {:#}", lid.head, err),
+            ),
+        });
+    }
+
+    match current_heads(backend, &config) {
+        Ok((heads, ignored_heads)) => {
+            checks.push(Check::new(
+                CheckStatus::Pass,
+                format!(
+                    "Backend reports {} heads ({} ignored)",
+                    heads.len() + ignored_heads.len(),
+                    ignored_heads.len()
+                ),
+            ));
+            for head in heads.iter().chain(&ignored_heads) {
+                let identified_by_name =
+                    config.connector_name_fallback && head.name.as_ref() == Some(&head.serial);
+                if !identified_by_name
+                    && (head.make.is_empty() || head.model.is_empty() || head.serial.is_empty())
+                {
+                    checks.push(Check::new(
+                        CheckStatus::Warn,
+                        format!(
+                            "Head {} has an empty make, model or serial ({:?} {:?} {:?}), \
+                             so it cannot be told apart from similar monitors",
+                            head.name.as_deref().unwrap_or_default(),
+                            head.make,
+                            head.model,
+                            head.serial
+                        ),
+                    ));
+                }
+            }
+        }
+        Err(err) => checks.push(Check::new(
+            CheckStatus::Fail,
+            format!("Backend does not work: {:#}", err),
+        )),
+    }
+
+    match dirs::state_dir() {
+        Ok(state_dir) => checks.push(Check::new(
+            CheckStatus::Pass,
+            format!("State directory is {}", state_dir.display()),
+        )),
+        Err(err) => checks.push(Check::new(
+            CheckStatus::Fail,
+            format!("No state directory: {:#}", err),
+        )),
+    }
+
+    match check_saved_profiles(&config) {
+        Ok(files) => {
+            checks.push(Check::new(
+                CheckStatus::Pass,
+                format!("Found {} saved profiles", files.len()),
+            ));
+            for file in files {
+                for problem in file.problems {
+                    checks.push(Check::new(
+                        CheckStatus::Warn,
+                        format!("Saved profile {}: {}", file.path.display(), problem),
+                    ));
+                }
+            }
+        }
+        Err(err) => checks.push(Check::new(
+            CheckStatus::Fail,
+            format!("Saved profiles cannot be listed: {:#}", err),
+        )),
+    }
+
+    checks
+}
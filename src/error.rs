@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+/// Errors callers may want to tell apart
+///
+/// Functions in this crate return `anyhow::Error`, which may wrap one of these;
+/// use `Error::find` to get at it.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No screen config saved at {}", path.display())]
+    ProfileNotFound { path: PathBuf },
+
+    #[error(
+        "Profile {profile} was already saved at {} on {}; use --force to overwrite it",
+        path.display(),
+        humantime::format_rfc3339_seconds(*saved_at)
+    )]
+    ProfileExists {
+        profile: String,
+        path: PathBuf,
+        saved_at: std::time::SystemTime,
+    },
+
+    #[error("Screen config {path} does not match connected heads ({reason})")]
+    ConfigMismatch { path: String, reason: String },
+
+    #[error("executable '{executable}' not found; install it or pass --executable")]
+    ExecutableNotFound { executable: String },
+
+    #[error("{executable} failed")]
+    BackendFailed { executable: String },
+
+    #[error("cannot change monitors in read-only mode")]
+    ReadOnly,
+
+    #[error(
+        "another wlscsr process holds the lock at {}; try again later or pass --no-lock",
+        path.display()
+    )]
+    Locked { path: PathBuf },
+}
+
+impl Error {
+    /// The first `Error` in the chain of `err`, if any
+    pub fn find(err: &anyhow::Error) -> Option<&Self> {
+        err.chain().find_map(|e| e.downcast_ref::<Self>())
+    }
+
+    /// Short, stable identifier of the kind of error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ProfileNotFound { .. } => "profile_not_found",
+            Error::ProfileExists { .. } => "profile_exists",
+            Error::ConfigMismatch { .. } => "config_mismatch",
+            Error::ExecutableNotFound { .. } => "executable_not_found",
+            Error::BackendFailed { .. } => "backend_failed",
+            Error::ReadOnly => "read_only",
+            Error::Locked { .. } => "locked",
+        }
+    }
+
+    /// The file or executable the error relates to
+    pub fn path(&self) -> String {
+        match self {
+            Error::ProfileNotFound { path }
+            | Error::ProfileExists { path, .. }
+            | Error::Locked { path } => path.display().to_string(),
+            Error::ConfigMismatch { path, .. } => path.clone(),
+            Error::ExecutableNotFound { executable } | Error::BackendFailed { executable } => {
+                executable.clone()
+            }
+            Error::ReadOnly => String::new(),
+        }
+    }
+}
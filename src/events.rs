@@ -0,0 +1,112 @@
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::dirs;
+
+/// State file the daemon appends events to, one JSON object per line
+const LOG_FILE: &str = "events.jsonl";
+/// State file the event log is moved to when it grows too large, replacing
+/// the previous one
+const ROTATED_LOG_FILE: &str = "events.jsonl.1";
+/// Size in bytes beyond which the event log is rotated
+const MAX_LOG_SIZE: u64 = 256 * 1024;
+
+/// Something the daemon (`serve` or `watch`) did
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Event {
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The set of connected heads changed
+    HeadsChanged { heads: usize },
+    /// A saved config was applied. `fallback` is set if it is not of the
+    /// requested profile, but one of the `fallback_profiles`.
+    Applied { profile: String, fallback: bool },
+    /// Applying a config failed
+    Error { message: String },
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventKind::HeadsChanged { heads } => write!(f, "{} heads connected", heads),
+            EventKind::Applied {
+                profile,
+                fallback: false,
+            } => write!(f, "applied profile {}", profile),
+            EventKind::Applied {
+                profile,
+                fallback: true,
+            } => write!(f, "applied fallback profile {}", profile),
+            EventKind::Error { message } => write!(f, "error: {}", message),
+        }
+    }
+}
+
+impl Event {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.time)
+    }
+}
+
+/// Append an event to the event log, rotating it if it has grown too large.
+/// Failures are logged, but otherwise ignored.
+pub fn record(kind: EventKind) {
+    if let Err(err) = try_record(kind) {
+        warn!(%err, "Failed to record event");
+    }
+}
+
+fn try_record(kind: EventKind) -> anyhow::Result<()> {
+    let event = Event {
+        time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        kind,
+    };
+    debug!(?event, "Recording event");
+
+    let path = dirs::place_state_file(LOG_FILE)?;
+    if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() >= MAX_LOG_SIZE) {
+        debug!(path = %path.display(), "Rotating event log");
+        std::fs::rename(&path, dirs::state_file(ROTATED_LOG_FILE)?)?;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&event)?)?;
+    Ok(())
+}
+
+/// Events recorded at or after `since` (or all of them), oldest first
+pub fn read_events(since: Option<SystemTime>) -> anyhow::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for name in [ROTATED_LOG_FILE, LOG_FILE] {
+        let path = dirs::state_file(name)?;
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for line in std::io::BufReader::new(file).lines() {
+            match serde_json::from_str::<Event>(&line?) {
+                Ok(event) => events.push(event),
+                Err(err) => debug!(path = %path.display(), %err, "Skipping invalid event"),
+            }
+        }
+    }
+
+    if let Some(since) = since {
+        events.retain(|event| event.time() >= since);
+    }
+    Ok(events)
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use crate::types::SavedConfig;
+
+/// Format screen configs are read and written in outside the state directory,
+/// which always holds JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Json,
+    /// Requires wlscsr to be built with the `yaml` feature
+    Yaml,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            _ => Err(format!("invalid format {:?}, must be json or yaml", s)),
+        }
+    }
+}
+
+impl Format {
+    /// The format of a file or URL by its extension: YAML for `.yaml` and
+    /// `.yml`, JSON for anything else
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    /// Extension of files in this format, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    /// Parse a saved screen config in this format
+    pub fn parse(self, contents: &[u8]) -> anyhow::Result<SavedConfig> {
+        match self {
+            Format::Json => Ok(SavedConfig::from_slice(contents)?),
+            Format::Yaml => parse_yaml(contents),
+        }
+    }
+
+    /// Write a saved screen config in this format
+    pub fn write(self, saved: &SavedConfig) -> anyhow::Result<String> {
+        match self {
+            Format::Json => Ok(serde_json::to_string_pretty(saved)?),
+            Format::Yaml => write_yaml(saved),
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(contents: &[u8]) -> anyhow::Result<SavedConfig> {
+    Ok(serde_yaml::from_slice(contents)?)
+}
+
+#[cfg(feature = "yaml")]
+fn write_yaml(saved: &SavedConfig) -> anyhow::Result<String> {
+    Ok(serde_yaml::to_string(saved)?)
+}
+
+#[cfg(not(feature = "yaml"))]
+fn parse_yaml(_contents: &[u8]) -> anyhow::Result<SavedConfig> {
+    Err(yaml_unsupported())
+}
+
+#[cfg(not(feature = "yaml"))]
+fn write_yaml(_saved: &SavedConfig) -> anyhow::Result<String> {
+    Err(yaml_unsupported())
+}
+
+#[cfg(not(feature = "yaml"))]
+fn yaml_unsupported() -> anyhow::Error {
+    anyhow::anyhow!("cannot use YAML: wlscsr was built without the yaml feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::tests::{head, head_config};
+    use crate::types::{Compositor, Head, Transform};
+
+    fn saved_config() -> SavedConfig {
+        SavedConfig {
+            compositor: Some(Compositor {
+                name: "Hyprland".to_string(),
+                version: "0.50.0".to_string(),
+            }),
+            heads: vec![
+                Head {
+                    name: None,
+                    extra_commands: vec!["keyword workspace 1,monitor:DP-1".to_string()],
+                    ..head("DP-1", "1", Some(head_config(Transform::Rotate90, 1.5)))
+                },
+                Head {
+                    name: None,
+                    ..head("DP-2", "2", None)
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(Format::from_path("work.yaml"), Format::Yaml);
+        assert_eq!(Format::from_path("dir.yml/work.yml"), Format::Yaml);
+        assert_eq!(Format::from_path("work.json"), Format::Json);
+        assert_eq!(Format::from_path("https://example.com/work"), Format::Json);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let saved = saved_config();
+        let written = Format::Json.write(&saved).unwrap();
+        assert_eq!(Format::Json.parse(written.as_bytes()).unwrap(), saved);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_round_trip() {
+        let saved = saved_config();
+        let written = Format::Yaml.write(&saved).unwrap();
+        assert!(written.contains("make: Dell Inc."), "{}", written);
+        assert_eq!(Format::Yaml.parse(written.as_bytes()).unwrap(), saved);
+    }
+
+    #[cfg(not(feature = "yaml"))]
+    #[test]
+    fn yaml_without_feature() {
+        let err = Format::Yaml.write(&saved_config()).unwrap_err();
+        assert!(err.to_string().contains("yaml feature"), "{}", err);
+    }
+}
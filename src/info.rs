@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::types::{AvailableMode, Head, HeadConfig};
+
+/// Summary of the connected heads, as printed by `wlscsr info`
+#[derive(Serialize, Debug)]
+pub struct Info {
+    pub heads: Vec<InfoHead>,
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InfoHead {
+    pub name: String,
+    pub make: String,
+    pub model: String,
+    pub serial: String,
+    pub ignored: bool,
+    pub config: Option<HeadConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modes: Option<Vec<AvailableMode>>,
+}
+
+impl Info {
+    pub fn new(heads: &[Head], ignored_heads: &[Head], path: PathBuf) -> Self {
+        let heads = heads
+            .iter()
+            .map(|h| (h, false))
+            .chain(ignored_heads.iter().map(|h| (h, true)))
+            .map(|(h, ignored)| InfoHead {
+                name: h.name.clone().unwrap_or_default(),
+                make: h.make.clone(),
+                model: h.model.clone(),
+                serial: h.serial.clone(),
+                ignored,
+                config: h.config.clone(),
+                modes: None,
+            })
+            .collect();
+
+        Self { heads, path }
+    }
+
+    /// Add the available modes of each head, as returned by
+    /// `Backend::get_available_modes`
+    pub fn set_modes(&mut self, mut modes: HashMap<String, Vec<AvailableMode>>) {
+        for head in self.heads.iter_mut() {
+            head.modes = Some(modes.remove(&head.name).unwrap_or_default());
+        }
+    }
+}
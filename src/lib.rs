@@ -1,2 +1,25 @@
+pub mod arrange;
 pub mod backend;
+pub mod bundle;
+pub mod config;
+pub mod dirs;
+pub mod doctor;
+pub mod error;
+pub mod events;
+pub mod format;
+pub mod info;
+pub mod lock;
+pub mod plan;
+pub mod power;
+pub mod presentation;
+pub mod profile;
+pub mod schema;
+pub mod service;
+pub mod source;
+pub mod status;
+pub mod toggle;
 pub mod types;
+pub mod undo;
+pub mod watch;
+
+pub use profile::{head_identity, layout_identity};
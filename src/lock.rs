@@ -0,0 +1,54 @@
+use std::fs::{File, TryLockError};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::dirs;
+use crate::error::Error;
+
+/// Name of the lock file in the state directory
+const LOCK_FILE: &str = "lock";
+
+/// How long to wait for the lock, or `None` not to lock at all
+static LOCK_TIMEOUT: RwLock<Option<Duration>> = RwLock::new(Some(Duration::from_secs(10)));
+
+/// Wait at most `timeout` for other wlscsr processes to release the state
+/// lock, or do not lock at all if `None`
+pub fn set_lock_timeout(timeout: Option<Duration>) {
+    *LOCK_TIMEOUT.write().unwrap() = timeout;
+}
+
+/// Advisory lock on the state directory, released when dropped
+pub struct StateLock {
+    _file: Option<File>,
+}
+
+/// Lock the state directory while changing heads or saved configs, so that
+/// concurrent invocations (including the daemon) take turns rather than
+/// interleaving their changes. Fails with `Error::Locked` if another process
+/// holds the lock for longer than the timeout set with `set_lock_timeout`.
+pub fn lock_state_dir() -> anyhow::Result<StateLock> {
+    let Some(timeout) = *LOCK_TIMEOUT.read().unwrap() else {
+        return Ok(StateLock { _file: None });
+    };
+    let path = dirs::place_state_file(LOCK_FILE)?;
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+
+    let start = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(StateLock { _file: Some(file) }),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                debug!(path = %path.display(), "State directory is locked, waiting");
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(TryLockError::WouldBlock) => return Err(Error::Locked { path }.into()),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::backend::{Backend, Capabilities};
+use crate::config::ConfigFile;
+use crate::profile::apply_head_config;
+use crate::types::{AvailableMode, Compositor, Head, HeadConfig, SavedConfig, WorkspaceAssignment};
+
+/// What restoring a saved config would do, without doing it
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub profile: String,
+    pub heads: Vec<PlannedHead>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedHead {
+    pub name: String,
+    pub action: PlannedAction,
+    /// Settings that would change, absent ones being `null`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlannedAction {
+    /// The head does not exist and would be created
+    Create,
+    Enable,
+    Disable,
+    Change,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+impl std::fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PlannedAction::Create => "create",
+            PlannedAction::Enable => "enable",
+            PlannedAction::Disable => "disable",
+            PlannedAction::Change => "change",
+            PlannedAction::Unchanged => "unchanged",
+        })
+    }
+}
+
+/// Work out what `apply_head_config` would do with `saved` for the connected
+/// `heads` (and `ignored_heads`), by running it against a backend that only
+/// records the changes it is asked to make
+pub fn plan_head_config(
+    backend: &dyn Backend,
+    profile: &str,
+    saved: &SavedConfig,
+    heads: &[Head],
+    ignored_heads: &[Head],
+    config: &ConfigFile,
+) -> anyhow::Result<Plan> {
+    let recorder = RecordingBackend {
+        inner: backend,
+        applied: Mutex::new(Vec::new()),
+        created: Mutex::new(Vec::new()),
+    };
+    apply_head_config(&recorder, saved, heads, config)?;
+    let applied = recorder.applied.into_inner().unwrap();
+    let created = recorder.created.into_inner().unwrap();
+
+    let mut warnings = Vec::new();
+    if let Some(ref saved_compositor) = saved.compositor
+        && let Ok(Some(compositor)) = backend.compositor()
+        && compositor != *saved_compositor
+    {
+        warnings.push(format!(
+            "config was saved with {}, running {}, the syntax may have changed",
+            saved_compositor, compositor
+        ));
+    }
+    let capabilities = backend.capabilities();
+    for saved_head in saved.heads.iter() {
+        let Some(ref name) = saved_head.name else {
+            continue;
+        };
+        for field in capabilities.unsupported(saved_head, &saved.heads) {
+            warnings.push(format!(
+                "{}: backend does not support {}, ignoring it",
+                name, field
+            ));
+        }
+        let saved_config = saved_head.config.as_ref();
+        let applied_config = applied
+            .iter()
+            .find(|h| h.name.as_ref() == Some(name))
+            .and_then(|h| h.config.as_ref());
+        if let (Some(saved_config), Some(applied_config)) = (saved_config, applied_config)
+            && !saved_config.max_refresh
+            && saved_config.mode_spec.is_none()
+            && saved_config.mode() != applied_config.mode()
+        {
+            warnings.push(format!(
+                "{}: saved mode {} is not available, falling back to {}",
+                name,
+                saved_config.mode(),
+                applied_config.mode()
+            ));
+        }
+    }
+    if saved.heads.iter().filter(|h| h.primary).count() > 1 {
+        warnings.push("more than one head is primary, using the first one".to_string());
+    }
+
+    let planned_heads = applied
+        .iter()
+        .filter_map(|head| {
+            let name = head.name.clone()?;
+            let current = heads
+                .iter()
+                .chain(ignored_heads)
+                .find(|h| h.name == head.name)
+                .and_then(|h| h.config.as_ref());
+            let changes = config_changes(current, head.config.as_ref());
+            let action = match (current, &head.config) {
+                _ if created.contains(&name) => PlannedAction::Create,
+                (None, Some(_)) => PlannedAction::Enable,
+                (Some(_), None) => PlannedAction::Disable,
+                _ if changes.is_empty() => PlannedAction::Unchanged,
+                _ => PlannedAction::Change,
+            };
+            Some(PlannedHead {
+                name,
+                action,
+                changes,
+            })
+        })
+        .collect();
+
+    Ok(Plan {
+        profile: profile.to_string(),
+        heads: planned_heads,
+        warnings,
+    })
+}
+
+/// The settings that differ between `from` and `to`
+fn config_changes(from: Option<&HeadConfig>, to: Option<&HeadConfig>) -> Vec<FieldChange> {
+    let fields = |config: Option<&HeadConfig>| match config.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let (from, to) = (fields(from), fields(to));
+    let mut names: Vec<&String> = from.keys().chain(to.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old = from.get(name).cloned().unwrap_or_default();
+            let new = to.get(name).cloned().unwrap_or_default();
+            (old != new).then(|| FieldChange {
+                field: name.clone(),
+                from: old,
+                to: new,
+            })
+        })
+        .collect()
+}
+
+/// Passes through queries to another backend, but only records the changes
+/// it is asked to make
+struct RecordingBackend<'a> {
+    inner: &'a dyn Backend,
+    applied: Mutex<Vec<Head>>,
+    created: Mutex<Vec<String>>,
+}
+
+impl Backend for RecordingBackend<'_> {
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+        self.inner.get_all_heads()
+    }
+
+    fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+        self.inner.get_available_modes()
+    }
+
+    fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
+        *self.applied.lock().unwrap() = heads.to_vec();
+        Ok(())
+    }
+
+    fn fallback_head_config(
+        &self,
+        _active_head_names: &[String],
+        _inactive_head_names: &[String],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn compositor(&self) -> anyhow::Result<Option<Compositor>> {
+        self.inner.compositor()
+    }
+
+    fn get_workspaces(&self) -> anyhow::Result<Vec<WorkspaceAssignment>> {
+        self.inner.get_workspaces()
+    }
+
+    fn move_workspaces(&self, _workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn bind_workspaces(&self, _workspaces: &[WorkspaceAssignment]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn create_head(&self, name: &str) -> anyhow::Result<()> {
+        self.created.lock().unwrap().push(name.to_string());
+        Ok(())
+    }
+
+    fn focus_head(&self, _head: &Head) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
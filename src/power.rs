@@ -0,0 +1,22 @@
+use tracing::debug;
+
+/// Directory the kernel lists power supplies in
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// Whether the system runs on battery, i.e. none of the power supplies that
+/// report whether they are online (AC adapters, USB) is
+///
+/// Systems without such power supplies, e.g. desktops, are not on battery.
+pub fn on_battery() -> bool {
+    let Ok(entries) = std::fs::read_dir(POWER_SUPPLY_DIR) else {
+        return false;
+    };
+
+    let online: Vec<bool> = entries
+        .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("online")).ok())
+        .map(|contents| contents.trim() == "1")
+        .collect();
+    debug!(?online, "Read power supply state");
+
+    !online.is_empty() && !online.contains(&true)
+}
@@ -0,0 +1,100 @@
+use tracing::{debug, info, warn};
+
+use crate::backend::Backend;
+use crate::config::ConfigFile;
+use crate::profile::{apply_head_config, current_heads, load_head_config_with_fallbacks};
+use crate::types::{HeadConfig, Transform};
+
+/// Turn on presentation mode: mirror the primary head (or the first enabled
+/// one, if there is no primary head) onto all other heads, enabling them if
+/// needed, with VRR off everywhere. Heads ignored because of a closed lid are
+/// left alone.
+pub fn presentation_on(backend: &dyn Backend, config: &ConfigFile) -> anyhow::Result<()> {
+    let (mut heads, _) = current_heads(backend, config)?;
+    let source = heads
+        .iter()
+        .find(|h| h.primary && h.config.is_some())
+        .or_else(|| heads.iter().find(|h| h.config.is_some()))
+        .and_then(|h| Some((h.name.clone()?, h.config.clone()?)))
+        .ok_or_else(|| anyhow::anyhow!("no head is enabled to be mirrored"))?;
+    let (source_name, source_config) = source;
+    info!(head = source_name, "Mirroring head onto all others");
+
+    let mut modes = backend.get_available_modes()?;
+    for head in heads.iter_mut() {
+        let Some(name) = head.name.clone() else {
+            continue;
+        };
+        if name == source_name {
+            if let Some(ref mut head_config) = head.config {
+                head_config.vrr = false;
+            }
+            continue;
+        }
+
+        let head_config = match head.config.take() {
+            Some(head_config) => head_config,
+            None => {
+                let head_modes = modes.remove(&name).unwrap_or_default();
+                let Some(mode) = head_modes
+                    .iter()
+                    .find(|m| m.preferred)
+                    .or_else(|| config.mode_policy.pick(&head_modes))
+                else {
+                    debug!(head = name, "No modes known, leaving head disabled");
+                    continue;
+                };
+                HeadConfig {
+                    width: mode.mode.width,
+                    height: mode.mode.height,
+                    refresh_rate: mode.mode.refresh_rate,
+                    x: 0,
+                    y: 0,
+                    scale: 1.0,
+                    transform: Transform::Normal,
+                    vrr: false,
+                    custom_mode: None,
+                    bitdepth: None,
+                    cm: None,
+                    sdr_brightness: None,
+                    sdr_saturation: None,
+                    tearing: None,
+                    mirror_of: None,
+                    max_refresh: false,
+                    auto_position: false,
+                    mode_spec: None,
+                }
+            }
+        };
+        head.config = Some(HeadConfig {
+            x: source_config.x,
+            y: source_config.y,
+            vrr: false,
+            mirror_of: Some(source_name.clone()),
+            ..head_config
+        });
+    }
+
+    let capabilities = backend.capabilities();
+    for head in heads.iter() {
+        for field in capabilities.unsupported(head, &heads) {
+            warn!(
+                head = head.name.as_deref().unwrap_or_default(),
+                field, "Backend does not support this setting, ignoring it"
+            );
+        }
+    }
+    backend.set_head_config(&heads)
+}
+
+/// Turn off presentation mode by restoring the saved config of `profile` (or
+/// a fallback profile) for the connected heads
+pub fn presentation_off(
+    backend: &dyn Backend,
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<()> {
+    let (heads, ignored_heads) = current_heads(backend, config)?;
+    let (_, saved) = load_head_config_with_fallbacks(&heads, &ignored_heads, profile, config)?;
+    apply_head_config(backend, &saved, &heads, config)
+}
@@ -0,0 +1,2208 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, instrument, warn};
+
+use crate::arrange::detect_auto_positions;
+use crate::backend::Backend;
+use crate::config::{AmbiguousHeads, ConfigFile, HashAlgorithm, ModeDegradation, ModePolicy};
+use crate::dirs;
+use crate::error::Error;
+use crate::format::Format;
+use crate::power;
+use crate::status::LastApplied;
+use crate::types::{AvailableMode, Head, HeadMode, ModeSpec, SavedConfig, WorkspaceAssignment};
+
+/// Name of the profile used when none is given explicitly
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Normalize make, model and serial of the heads as configured, after
+/// replacing makes and models with their aliases
+///
+/// With `connector_name_fallback`, heads with empty make, model and serial get
+/// their connector name as serial, so that it is saved, hashed and matched as
+/// their identity.
+pub fn normalize_heads(heads: &mut [Head], config: &ConfigFile) {
+    let normalization = config.identity_normalization;
+    for head in heads.iter_mut() {
+        head.make = normalization.normalize(config.alias(&head.make));
+        head.model = normalization.normalize(config.alias(&head.model));
+        head.serial = normalization.normalize(&head.serial);
+
+        if config.connector_name_fallback
+            && head.make.is_empty()
+            && head.model.is_empty()
+            && head.serial.is_empty()
+            && let Some(ref name) = head.name
+        {
+            head.serial = name.clone();
+        }
+    }
+}
+
+/// Query the backend for all heads and split them into active heads (sorted by
+/// make, model and serial) and heads that are ignored because of a closed lid
+pub fn current_heads(
+    backend: &dyn Backend,
+    config: &ConfigFile,
+) -> anyhow::Result<(Vec<Head>, Vec<Head>)> {
+    let ignored_head_patterns = config.ignored_head_patterns();
+
+    let mut all_heads = backend.get_all_heads()?;
+    normalize_heads(&mut all_heads, config);
+
+    let mut ignored_heads = Vec::new();
+    let mut heads: Vec<Head> = all_heads
+        .into_iter()
+        .filter_map(|h| {
+            if h.name
+                .as_ref()
+                .map(|name| ignored_head_patterns.iter().any(|p| p.matches(name)))
+                .unwrap_or(false)
+            {
+                ignored_heads.push(h);
+                None
+            } else {
+                Some(h)
+            }
+        })
+        .collect();
+    heads.sort_by(|a, b| a.cmp_mms(b).then_with(|| a.name.cmp(&b.name)));
+
+    Ok((heads, ignored_heads))
+}
+
+/// Hash identifying a set of heads
+///
+/// Only make, model and serial are hashed, unless `hash_connector_names` is
+/// set in the config, in which case connector names are included as well.
+pub fn hash_heads(heads: &[Head], config: &ConfigFile) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(heads.len().to_le_bytes());
+
+    for head in heads {
+        for s in [&head.make, &head.model, &head.serial] {
+            let bytes = s.as_bytes();
+            hasher.update(bytes.len().to_le_bytes());
+            hasher.update(bytes);
+        }
+        if config.hash_connector_names {
+            let bytes = head.name.as_deref().unwrap_or_default().as_bytes();
+            hasher.update(bytes.len().to_le_bytes());
+            hasher.update(bytes);
+        }
+    }
+
+    hasher.finalize().into()
+}
+
+/// Hash identifying a set of heads, as used in file names, written as
+/// configured with `hash`
+pub fn heads_hash(heads: &[Head], config: &ConfigFile) -> String {
+    config.hash.encode(&hash_heads(heads, config))
+}
+
+/// Hash identifying the layout of the given heads, as in the names of saved
+/// config files, for other programs to key their own state on
+///
+/// The heads are normalized and sorted as configured in `config` (see
+/// `read_config_file`), so they can be passed as queried from the backend,
+/// leaving out any that are ignored because of a closed lid.
+pub fn layout_identity(heads: &[Head], config: &ConfigFile) -> String {
+    let mut heads = heads.to_vec();
+    normalize_heads(&mut heads, config);
+    heads.sort_by(|a, b| a.cmp_mms(b).then_with(|| a.name.cmp(&b.name)));
+    heads_hash(&heads, config)
+}
+
+/// Like `layout_identity`, for a single head
+pub fn head_identity(head: &Head, config: &ConfigFile) -> String {
+    layout_identity(std::slice::from_ref(head), config)
+}
+
+/// File name of the saved screen config for the given heads and profile
+///
+/// The default profile is stored as `<hash>.json`, any other profile as
+/// `<hash>-<profile>.json`.
+pub fn profile_file_name(
+    heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<String> {
+    hashed_profile_file_name(&heads_hash(heads, config), profile)
+}
+
+/// Like `profile_file_name`, but for heads with the given (hex encoded) hash
+pub fn hashed_profile_file_name(hash: &str, profile: &str) -> anyhow::Result<String> {
+    if profile.is_empty() || profile.contains(['/', '\0']) {
+        return Err(anyhow::anyhow!("Invalid profile name {:?}", profile));
+    }
+
+    Ok(if profile == DEFAULT_PROFILE {
+        format!("{}.json", hash)
+    } else {
+        format!("{}-{}.json", hash, profile)
+    })
+}
+
+/// Directories searched for saved screen configs, in this order: the profile
+/// directory given on the command line, or else in the config file, then the
+/// state directory
+fn profile_dirs(config: &ConfigFile) -> anyhow::Result<Vec<PathBuf>> {
+    let mut profile_dirs: Vec<PathBuf> = dirs::profile_dir()
+        .or_else(|| config.profile_dir.clone())
+        .into_iter()
+        .collect();
+    profile_dirs.push(dirs::state_dir()?);
+    Ok(profile_dirs)
+}
+
+/// Paths of all files in the directories searched for saved screen configs
+fn list_profile_files(config: &ConfigFile) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for dir in profile_dirs(config)? {
+        files.extend(dirs::list_files(&dir)?);
+    }
+    Ok(files)
+}
+
+/// Paths of the saved screen configs that are found, i.e. of all files in
+/// the directories searched for them, leaving out those shadowed by a file of
+/// the same name in an earlier directory
+pub fn list_saved_profile_files(config: &ConfigFile) -> anyhow::Result<Vec<PathBuf>> {
+    let mut names = std::collections::HashSet::new();
+    Ok(list_profile_files(config)?
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| names.insert(name.to_owned()))
+        })
+        .collect())
+}
+
+/// Directory screen configs are saved to: the profile directory if given on
+/// the command line, otherwise the state directory
+fn save_dir() -> anyhow::Result<PathBuf> {
+    match dirs::profile_dir() {
+        Some(dir) => Ok(dir),
+        None => dirs::state_dir(),
+    }
+}
+
+/// Path of the saved screen config for the given heads and profile, in the
+/// first of `profile_dirs` that has it, or else where it would be saved
+///
+/// If there is no such file, but one named with the full SHA-256 hash, as
+/// saved before `hash` was changed, that one is used instead.
+pub fn profile_path(heads: &[Head], profile: &str, config: &ConfigFile) -> anyhow::Result<PathBuf> {
+    let file_name = profile_file_name(heads, profile, config)?;
+    let legacy_file_name = (config.hash != HashAlgorithm::Sha256)
+        .then(|| {
+            let legacy_hash = HashAlgorithm::Sha256.encode(&hash_heads(heads, config));
+            hashed_profile_file_name(&legacy_hash, profile)
+        })
+        .transpose()?;
+
+    for dir in profile_dirs(config)? {
+        let path = dir.join(&file_name);
+        if path.exists() {
+            return Ok(path);
+        }
+        if let Some(ref legacy_file_name) = legacy_file_name {
+            let legacy_path = dir.join(legacy_file_name);
+            if legacy_path.exists() {
+                debug!(path = %legacy_path.display(), "Using screen config named with SHA-256 hash");
+                return Ok(legacy_path);
+            }
+        }
+    }
+    Ok(save_dir()?.join(file_name))
+}
+
+/// Rename the saved profile `from` to `to`, for the given heads or, if given,
+/// the heads with the given hash. `from` is looked up like a profile to be
+/// restored, and renamed to where `to` would be saved. Fails if `to` has been
+/// saved before, unless `overwrite` is set. Returns the new path of the
+/// profile.
+pub fn rename_profile(
+    heads: &[Head],
+    hash: Option<&str>,
+    from: &str,
+    to: &str,
+    config: &ConfigFile,
+    overwrite: bool,
+) -> anyhow::Result<PathBuf> {
+    let (from_path, existing_path, to_file_name) = match hash {
+        Some(hash)
+            if HashAlgorithm::from_hex_digits(hash.len()).is_some()
+                && hash.bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            (
+                hashed_profile_path(hash, from, config)?,
+                hashed_profile_path(hash, to, config)?,
+                hashed_profile_file_name(hash, to)?,
+            )
+        }
+        Some(hash) => return Err(anyhow::anyhow!("Invalid hash {:?}", hash)),
+        None => (
+            profile_path(heads, from, config)?,
+            profile_path(heads, to, config)?,
+            profile_file_name(heads, to, config)?,
+        ),
+    };
+    let dir = save_dir()?;
+    let to_path = dir.join(to_file_name);
+
+    if !from_path.exists() {
+        return Err(Error::ProfileNotFound { path: from_path }.into());
+    }
+    if !overwrite && let Ok(metadata) = std::fs::metadata(&existing_path) {
+        return Err(Error::ProfileExists {
+            profile: to.to_string(),
+            path: existing_path,
+            saved_at: metadata.modified()?,
+        }
+        .into());
+    }
+
+    debug!(from = %from_path.display(), to = %to_path.display(), "Renaming profile");
+    std::fs::create_dir_all(&dir)?;
+    match std::fs::rename(&from_path, &to_path) {
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            std::fs::copy(&from_path, &to_path)?;
+            std::fs::remove_file(&from_path)?;
+        }
+        result => result?,
+    }
+    Ok(to_path)
+}
+
+/// Path of the saved screen config for the heads with the given hash, in the
+/// first of `profile_dirs` that has it, or else where it would be saved
+fn hashed_profile_path(hash: &str, profile: &str, config: &ConfigFile) -> anyhow::Result<PathBuf> {
+    let file_name = hashed_profile_file_name(hash, profile)?;
+    for dir in profile_dirs(config)? {
+        let path = dir.join(&file_name);
+        if path.exists() {
+            return Ok(path);
+        }
+    }
+    Ok(save_dir()?.join(file_name))
+}
+
+/// Names of all profiles saved for the given heads
+pub fn saved_profiles(heads: &[Head], config: &ConfigFile) -> anyhow::Result<Vec<String>> {
+    let digest = hash_heads(heads, config);
+    let hashes = [config.hash, HashAlgorithm::Sha256].map(|algorithm| algorithm.encode(&digest));
+
+    let mut profiles: Vec<String> = list_profile_files(config)?
+        .into_iter()
+        .filter_map(|path| {
+            let (hash, profile) = parse_profile_file_name(&path)?;
+            hashes.contains(&hash).then_some(profile)
+        })
+        .collect();
+    profiles.sort();
+    profiles.dedup();
+
+    Ok(profiles)
+}
+
+/// The profile saved for the given heads that was restored last (see
+/// `LastApplied`), or else the one saved last, if any
+pub fn newest_profile(heads: &[Head], config: &ConfigFile) -> anyhow::Result<Option<String>> {
+    let profiles = saved_profiles(heads, config)?;
+    if let Some(last_applied) = LastApplied::read()
+        && last_applied.hash == Some(heads_hash(heads, config))
+        && profiles.contains(&last_applied.profile)
+    {
+        return Ok(Some(last_applied.profile));
+    }
+
+    let mut newest: Option<(String, std::time::SystemTime)> = None;
+    for profile in profiles {
+        let modified = std::fs::metadata(profile_path(heads, &profile, config)?)?.modified()?;
+        if newest.as_ref().is_none_or(|(_, newest)| modified > *newest) {
+            newest = Some((profile, modified));
+        }
+    }
+    Ok(newest.map(|(profile, _)| profile))
+}
+
+/// Hash and profile name of a saved screen config file, or `None` if the path
+/// is not one
+pub fn parse_profile_file_name(path: &std::path::Path) -> Option<(String, String)> {
+    let name = path.file_name()?.to_str()?.strip_suffix(".json")?;
+    let (hash, profile) = name.split_once('-').unwrap_or((name, DEFAULT_PROFILE));
+    if HashAlgorithm::from_hex_digits(hash.len()).is_none()
+        || !hash.bytes().all(|b| b.is_ascii_hexdigit())
+        || profile.is_empty()
+    {
+        return None;
+    }
+    Some((hash.to_string(), profile.to_string()))
+}
+
+/// The hash identifying saved heads, as used in the name of the file they are
+/// saved in, written like `file_hash` (the hash in the file's name), so that
+/// files saved with either setting of `hash` can be checked
+///
+/// Returns `None` with `hash_connector_names`, since connector names are not
+/// saved, so the hash cannot be recomputed.
+pub fn saved_heads_hash(
+    mut heads: Vec<Head>,
+    file_hash: &str,
+    config: &ConfigFile,
+) -> Option<String> {
+    if config.hash_connector_names {
+        return None;
+    }
+    normalize_heads(&mut heads, config);
+    heads.sort_by(Head::cmp_mms);
+    let algorithm = HashAlgorithm::from_hex_digits(file_hash.len()).unwrap_or(config.hash);
+    Some(algorithm.encode(&hash_heads(&heads, config)))
+}
+
+/// A saved screen config file found in the profile or state directory
+pub struct SavedProfileFile {
+    pub path: PathBuf,
+    /// Hash of the heads, according to the file name
+    pub hash: String,
+    /// Profile name, according to the file name
+    pub profile: String,
+    /// Reasons why the file may not be found or may shadow another one
+    pub problems: Vec<String>,
+}
+
+/// List all saved screen config files, checking that the name of each file
+/// matches the hash of the heads saved in it and that no two files would be
+/// found for the same heads
+pub fn check_saved_profiles(config: &ConfigFile) -> anyhow::Result<Vec<SavedProfileFile>> {
+    let mut files: Vec<(SavedProfileFile, Option<String>)> = list_profile_files(config)?
+        .into_iter()
+        .filter_map(|path| {
+            let (hash, profile) = parse_profile_file_name(&path)?;
+            Some(SavedProfileFile {
+                hash,
+                profile,
+                problems: Vec::new(),
+                path,
+            })
+        })
+        .map(|mut file| {
+            let heads = std::fs::read(&file.path)
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| Ok(SavedConfig::from_slice(&contents)?.heads));
+            let computed_hash = match heads {
+                Ok(heads) => {
+                    let computed_hash = saved_heads_hash(heads, &file.hash, config);
+                    if let Some(ref computed_hash) = computed_hash
+                        && *computed_hash != file.hash
+                    {
+                        file.problems.push(format!(
+                            "heads in file hash to {}, so it is never found",
+                            computed_hash
+                        ));
+                    }
+                    computed_hash
+                }
+                Err(err) => {
+                    file.problems.push(format!("cannot be read: {:#}", err));
+                    None
+                }
+            };
+            (file, computed_hash)
+        })
+        .collect();
+    files.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+
+    // Files holding the same heads under different names collide: only the
+    // one named after the hash is ever found
+    for idx in 0..files.len() {
+        let Some(ref computed_hash) = files[idx].1 else {
+            continue;
+        };
+        let colliding: Vec<String> = files
+            .iter()
+            .enumerate()
+            .filter(|(other, (file, other_hash))| {
+                *other != idx
+                    && file.profile == files[idx].0.profile
+                    && other_hash.as_ref() == Some(computed_hash)
+            })
+            .map(|(_, (file, _))| file.path.display().to_string())
+            .collect();
+        if !colliding.is_empty() {
+            files[idx]
+                .0
+                .problems
+                .push(format!("collides with {}", colliding.join(", ")));
+        }
+    }
+
+    Ok(files.into_iter().map(|(file, _)| file).collect())
+}
+
+/// Whether the live heads are configured as in the saved heads (as returned by
+/// `load_head_config`)
+pub fn config_matches(saved_heads: &[Head], heads: &[Head]) -> bool {
+    saved_heads.iter().all(|saved_head| {
+        heads
+            .iter()
+            .find(|h| h.name.is_some() && h.name == saved_head.name)
+            .map(|h| h.config == saved_head.config)
+            .unwrap_or(false)
+    })
+}
+
+/// Save the config of the heads under the given profile, in the profile
+/// directory if given on the command line, otherwise in the state directory.
+/// Fails if the profile has been saved before, unless `overwrite` is set. The
+/// compositor version, if the backend can tell, is saved along with the heads.
+///
+/// With `only_if_changed`, nothing is written if the saved config would be
+/// the same as the one already saved, which is indicated by returning `None`.
+/// A different saved config is still only replaced with `overwrite`.
+pub fn save_head_config(
+    backend: &dyn Backend,
+    heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+    overwrite: bool,
+    only_if_changed: bool,
+) -> anyhow::Result<Option<PathBuf>> {
+    if heads.iter().filter(|h| h.primary).count() > 1 {
+        return Err(anyhow::anyhow!("more than one head is primary"));
+    }
+
+    let dir = save_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(profile_file_name(heads, profile, config)?);
+    let metadata = std::fs::metadata(&path).ok();
+    debug!(path = %path.display(), "Saving screen config");
+
+    let previous = std::fs::read(&path)
+        .ok()
+        .and_then(|contents| SavedConfig::from_slice(&contents).ok());
+    let unchanged = previous.clone();
+    let saved = saved_config(backend, heads, previous, config);
+    if only_if_changed && unchanged.as_ref() == Some(&saved) {
+        debug!(path = %path.display(), "Screen config unchanged, not saving it");
+        return Ok(None);
+    }
+    if !overwrite && let Some(metadata) = metadata {
+        return Err(Error::ProfileExists {
+            profile: profile.to_string(),
+            path,
+            saved_at: metadata.modified()?,
+        }
+        .into());
+    }
+    if path.exists() {
+        let backup = backup_path(&path)?;
+        debug!(path = %backup.display(), "Backing up saved screen config");
+        std::fs::create_dir_all(backup.parent().unwrap())?;
+        std::fs::copy(&path, &backup)?;
+    }
+    std::fs::write(&path, profile_contents(&saved, config)?)?;
+
+    Ok(Some(path))
+}
+
+/// Where the previous version of the saved config at `path` is kept when it is
+/// overwritten: in the `backups` directory of the state directory, under the
+/// same name
+fn backup_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("invalid profile path {}", path.display()))?;
+    Ok(dirs::state_file(BACKUP_DIR)?.join(file_name))
+}
+
+/// Bring back the version of the profile saved for the heads before it was
+/// last overwritten. The version replaced by it becomes the backup in turn, so
+/// doing this twice undoes it. Returns the path of the profile.
+pub fn restore_backup(
+    heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<PathBuf> {
+    let path = save_dir()?.join(profile_file_name(heads, profile, config)?);
+    let backup = backup_path(&path)?;
+    if !backup.exists() {
+        return Err(anyhow::anyhow!(
+            "no backup of profile {} at {}",
+            profile,
+            backup.display()
+        ));
+    }
+
+    debug!(from = %backup.display(), to = %path.display(), "Restoring backup");
+    if path.exists() {
+        let swap = backup.with_extension("swap");
+        std::fs::rename(&path, &swap)?;
+        std::fs::rename(&backup, &path)?;
+        std::fs::rename(&swap, &backup)?;
+    } else {
+        std::fs::rename(&backup, &path)?;
+    }
+    Ok(path)
+}
+
+/// What `save_head_config` would write for the heads under the given
+/// profile, or the same in another format, without writing anything. The
+/// output can be given to `apply` as is.
+pub fn capture_head_config(
+    backend: &dyn Backend,
+    heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+    format: Format,
+) -> anyhow::Result<String> {
+    if heads.iter().filter(|h| h.primary).count() > 1 {
+        return Err(anyhow::anyhow!("more than one head is primary"));
+    }
+    let previous = std::fs::read(profile_path(heads, profile, config)?)
+        .ok()
+        .and_then(|contents| SavedConfig::from_slice(&contents).ok());
+    let saved = saved_config(backend, heads, previous, config);
+    match format {
+        Format::Json => profile_contents(&saved, config),
+        format => format.write(&saved),
+    }
+}
+
+/// The saved config of the heads. Settings that cannot be queried from the
+/// backend, but may have been added to the `previous` saved config by the
+/// user, are carried over.
+fn saved_config(
+    backend: &dyn Backend,
+    heads: &[Head],
+    previous: Option<SavedConfig>,
+    config: &ConfigFile,
+) -> SavedConfig {
+    let previous = previous.unwrap_or_default();
+    let mut previous_heads = previous.heads;
+    normalize_heads(&mut previous_heads, config);
+    let mut available_modes = None;
+    let mut heads: Vec<Head> = heads
+        .iter()
+        .map(|h| {
+            let mut h = h.clone();
+            let name = h.name.take().unwrap_or_default();
+            if let Some(previous) = previous_heads.iter().find(|p| p.same_mms(&h)) {
+                h.reserved = previous.reserved;
+                h.extra_commands = previous.extra_commands.clone();
+                h.create_if_missing = previous.create_if_missing;
+                h.mirror_group = previous.mirror_group.clone();
+                h.mirror_source = previous.mirror_source;
+                if let (Some(cfg), Some(previous_cfg)) = (&mut h.config, &previous.config) {
+                    cfg.bitdepth = cfg.bitdepth.or(previous_cfg.bitdepth);
+                    cfg.tearing = cfg.tearing.or(previous_cfg.tearing);
+                    cfg.cm = cfg.cm.take().or_else(|| previous_cfg.cm.clone());
+                    cfg.sdr_brightness = cfg.sdr_brightness.or(previous_cfg.sdr_brightness);
+                    cfg.sdr_saturation = cfg.sdr_saturation.or(previous_cfg.sdr_saturation);
+                    cfg.max_refresh = previous_cfg.max_refresh;
+                    // Only while the head is still in the mode the spec
+                    // stands for, rather than one set since
+                    cfg.mode_spec = previous_cfg.mode_spec.clone().filter(|spec| {
+                        let modes = available_modes.get_or_insert_with(|| {
+                            backend.get_available_modes().unwrap_or_else(|err| {
+                                warn!(%err, "Failed to query available modes");
+                                Default::default()
+                            })
+                        });
+                        let modes = modes.get(&name).map(Vec::as_slice).unwrap_or_default();
+                        resolve_mode_spec(spec, modes).is_some_and(|mode| mode.matches(&cfg.mode()))
+                    });
+                }
+            }
+            h
+        })
+        .collect();
+    if config.auto_positions {
+        detect_auto_positions(&mut heads);
+    }
+    let compositor = backend.compositor().unwrap_or_else(|err| {
+        warn!(%err, "Failed to query compositor version");
+        None
+    });
+    let default_head = heads
+        .iter()
+        .find(|h| h.primary && h.config.is_some())
+        .and_then(Head::identity_reference);
+    SavedConfig {
+        compositor,
+        heads,
+        hooks: previous.hooks,
+        default_head,
+    }
+}
+
+/// Contents of the file a saved config is written to, compact if so configured
+fn profile_contents(saved: &SavedConfig, config: &ConfigFile) -> anyhow::Result<String> {
+    Ok(if config.compact_json {
+        serde_json::to_string(saved)?
+    } else {
+        serde_json::to_string_pretty(saved)?
+    })
+}
+
+/// A saved config rewritten by `replace_serial`
+pub struct SerialReplacement {
+    pub from: PathBuf,
+    /// Differs from `from` if the hash in the file name changed
+    pub to: PathBuf,
+}
+
+/// Replace the serial `old` of heads with the given make and model by `new`
+/// in all saved configs, e.g. after a monitor has been exchanged for the same
+/// model, renaming the files as the hash of their heads changes. Nothing is
+/// written if `dry_run` is set, or if any file would replace another one.
+pub fn replace_serial(
+    make: &str,
+    model: &str,
+    old: &str,
+    new: &str,
+    config: &ConfigFile,
+    dry_run: bool,
+) -> anyhow::Result<Vec<SerialReplacement>> {
+    if config.hash_connector_names {
+        return Err(anyhow::anyhow!(
+            "cannot rename saved configs with hash_connector_names, since connector names are not saved"
+        ));
+    }
+    let normalization = config.identity_normalization;
+    let make = normalization.normalize(config.alias(make));
+    let model = normalization.normalize(config.alias(model));
+    let old = normalization.normalize(old);
+
+    let mut rewrites = Vec::new();
+    for path in list_profile_files(config)? {
+        let Some((hash, profile)) = parse_profile_file_name(&path) else {
+            continue;
+        };
+        let Ok(mut saved) = std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(SavedConfig::from_slice(&contents)?))
+        else {
+            warn!(path = %path.display(), "Cannot read saved screen config, skipping it");
+            continue;
+        };
+
+        let mut normalized = saved.heads.clone();
+        normalize_heads(&mut normalized, config);
+        let mut replaced = false;
+        for (saved_head, head) in saved.heads.iter_mut().zip(&normalized) {
+            if head.make == make && head.model == model && head.serial == old {
+                if saved.default_head.is_some()
+                    && saved.default_head == saved_head.identity_reference()
+                {
+                    saved.default_head =
+                        Some(format!("{} {} {}", saved_head.make, saved_head.model, new));
+                }
+                saved_head.serial = new.to_string();
+                replaced = true;
+            }
+        }
+        if !replaced {
+            continue;
+        }
+
+        let new_hash = saved_heads_hash(saved.heads.clone(), &hash, config)
+            .expect("connector names not hashed");
+        let to = path.with_file_name(hashed_profile_file_name(&new_hash, &profile)?);
+        rewrites.push((SerialReplacement { from: path, to }, saved));
+    }
+
+    for (replacement, _) in &rewrites {
+        if replacement.to != replacement.from
+            && replacement.to.exists()
+            && !rewrites.iter().any(|(r, _)| r.from == replacement.to)
+        {
+            return Err(anyhow::anyhow!(
+                "{} would replace {}",
+                replacement.from.display(),
+                replacement.to.display()
+            ));
+        }
+    }
+
+    if !dry_run {
+        for (replacement, saved) in &rewrites {
+            debug!(from = %replacement.from.display(), to = %replacement.to.display(), "Replacing serial");
+            std::fs::write(&replacement.to, profile_contents(saved, config)?)?;
+        }
+        // Files that were renamed, unless another file has been renamed to
+        // their name
+        for (replacement, _) in &rewrites {
+            if !rewrites.iter().any(|(r, _)| r.to == replacement.from) {
+                std::fs::remove_file(&replacement.from)?;
+            }
+        }
+    }
+
+    Ok(rewrites
+        .into_iter()
+        .map(|(replacement, _)| replacement)
+        .collect())
+}
+
+#[instrument(skip_all, fields(profile = %profile))]
+pub fn load_head_config(
+    heads: &[Head],
+    ignored_heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<SavedConfig> {
+    if let Some(saved) = find_declared_head_config(heads, profile, config) {
+        let source = format!("profile {} in config file", profile);
+        return match_head_config(saved, heads, ignored_heads, config, &source);
+    }
+
+    let path = profile_path(heads, profile, config)?;
+    debug!(path = %path.display(), "Attempting to load screen config");
+    let contents = match std::fs::read(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return match find_head_config_with_missing_heads(heads, profile, config)? {
+                Some((path, saved)) => {
+                    match_head_config(saved, heads, ignored_heads, config, &path.display())
+                }
+                None => Err(Error::ProfileNotFound { path }.into()),
+            };
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let saved = SavedConfig::from_slice(&contents)?;
+    match_head_config(saved, heads, ignored_heads, config, &path.display())
+}
+
+/// Find the first profile declared in the config file with the given name,
+/// the same heads as the connected ones, and a `when` condition that holds,
+/// if any
+fn find_declared_head_config(
+    heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> Option<SavedConfig> {
+    config
+        .profiles
+        .iter()
+        .filter(|declared| declared.name == profile)
+        .filter(|declared| {
+            let mut declared_heads = declared.heads.clone();
+            normalize_heads(&mut declared_heads, config);
+            declared_heads.sort_by(Head::cmp_mms);
+            declared_heads.len() == heads.len()
+                && declared_heads.iter().zip(heads).all(|(d, h)| d.same_mms(h))
+        })
+        .find(|declared| declared.when.as_deref().is_none_or(condition_holds))
+        .map(|declared| {
+            debug!(profile, "Using screen config declared in config file");
+            SavedConfig {
+                compositor: None,
+                heads: declared.heads.clone(),
+                hooks: declared.hooks.clone(),
+                default_head: None,
+            }
+        })
+}
+
+/// Directory in the state directory where overwritten saved configs are kept
+const BACKUP_DIR: &str = "backups";
+
+/// How long the result of a `when` condition is reused, so that the daemon
+/// does not run it on every query
+const CONDITION_CACHE_TIME: Duration = Duration::from_secs(5);
+
+static CONDITION_CACHE: Mutex<Vec<(String, Instant, bool)>> = Mutex::new(Vec::new());
+
+/// Whether the shell command exits successfully, its output being discarded.
+/// Failing to run it counts as not holding.
+fn condition_holds(command: &str) -> bool {
+    let mut cache = CONDITION_CACHE.lock().unwrap();
+    cache.retain(|(_, time, _)| time.elapsed() < CONDITION_CACHE_TIME);
+    if let Some((_, _, holds)) = cache.iter().find(|(c, _, _)| c == command) {
+        return *holds;
+    }
+
+    debug!(command, "Checking profile condition");
+    let holds = match std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdout(std::process::Stdio::null())
+        .status()
+    {
+        Ok(status) => status.success(),
+        Err(err) => {
+            warn!(command, %err, "Failed to run profile condition");
+            false
+        }
+    };
+    cache.push((command.to_string(), Instant::now(), holds));
+    holds
+}
+
+/// Whether the saved head is to be created because no connected head matches
+/// it
+fn is_missing(saved_head: &Head, heads: &[Head]) -> bool {
+    saved_head.create_if_missing && !heads.iter().any(|h| h.same_mms(saved_head))
+}
+
+/// Find a saved config of `profile` that matches the connected heads once
+/// saved heads with `create_if_missing` that are not connected are left out
+///
+/// Such configs are saved under the hash of all heads including those to be
+/// created, so they cannot be found by the hash of the connected heads.
+fn find_head_config_with_missing_heads(
+    heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<Option<(PathBuf, SavedConfig)>> {
+    let hash = hash_heads(heads, config);
+
+    for path in list_profile_files(config)? {
+        if parse_profile_file_name(&path).map(|(_, p)| p) != Some(profile.to_string()) {
+            continue;
+        }
+        let Some(saved) = std::fs::read(&path)
+            .ok()
+            .and_then(|contents| SavedConfig::from_slice(&contents).ok())
+        else {
+            continue;
+        };
+        if !saved.heads.iter().any(|h| h.create_if_missing) {
+            continue;
+        }
+
+        let mut present: Vec<Head> = saved
+            .heads
+            .iter()
+            .filter(|h| !is_missing(h, heads))
+            .cloned()
+            .collect();
+        normalize_heads(&mut present, config);
+        present.sort_by(Head::cmp_mms);
+        if hash_heads(&present, config) == hash {
+            debug!(path = %path.display(), "Found screen config with heads to create");
+            return Ok(Some((path, saved)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Why the saved config of a profile does or does not match the connected
+/// heads
+pub struct Explanation {
+    pub hash: String,
+    pub path: PathBuf,
+    pub exists: bool,
+    /// Saved heads, normalized and sorted like the connected heads
+    pub saved_heads: Vec<Head>,
+    /// The outcome of matching, as returned by `load_head_config`
+    pub result: anyhow::Result<SavedConfig>,
+}
+
+pub fn explain_head_config(
+    heads: &[Head],
+    ignored_heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<Explanation> {
+    let path = profile_path(heads, profile, config)?;
+    let mut saved_heads = std::fs::read(&path)
+        .ok()
+        .and_then(|contents| SavedConfig::from_slice(&contents).ok())
+        .map(|saved| saved.heads)
+        .unwrap_or_default();
+    normalize_heads(&mut saved_heads, config);
+    saved_heads.sort_by(Head::cmp_mms);
+
+    Ok(Explanation {
+        hash: heads_hash(heads, config),
+        exists: path.exists(),
+        path,
+        saved_heads,
+        result: load_head_config(heads, ignored_heads, profile, config),
+    })
+}
+
+/// Match saved heads to the connected heads by make, model and serial, taking
+/// over the connector names of the connected heads. Ignored heads are added
+/// with their config removed, so that they get disabled, unless
+/// `leave_ignored_heads` is set.
+///
+/// `source` describes where the saved heads come from, for error messages.
+pub fn match_head_config(
+    saved: SavedConfig,
+    heads: &[Head],
+    ignored_heads: &[Head],
+    config: &ConfigFile,
+    source: &dyn std::fmt::Display,
+) -> anyhow::Result<SavedConfig> {
+    let mut saved = saved;
+    resolve_default_head(&mut saved);
+    let mut saved_heads = saved.heads;
+    normalize_heads(&mut saved_heads, config);
+
+    // Heads to be created are named after their serial, which is their
+    // connector name with `connector_name_fallback`
+    let (mut missing_heads, mut saved_heads): (Vec<Head>, Vec<Head>) = saved_heads
+        .into_iter()
+        .partition(|saved_head| is_missing(saved_head, heads));
+    for missing_head in missing_heads.iter_mut() {
+        missing_head.name = Some(missing_head.serial.clone());
+    }
+
+    if saved_heads.len() != heads.len() {
+        return Err(Error::ConfigMismatch {
+            path: source.to_string(),
+            reason: format!("{}!={}", saved_heads.len(), heads.len()),
+        }
+        .into());
+    }
+    saved_heads.sort_by(Head::cmp_mms);
+
+    for (idx, (saved_head, head)) in saved_heads.iter().zip(heads.iter()).enumerate() {
+        if !saved_head.same_mms(head) {
+            return Err(Error::ConfigMismatch {
+                path: source.to_string(),
+                reason: format!("idx {}", idx),
+            }
+            .into());
+        }
+    }
+    match_ambiguous_heads(&mut saved_heads, heads, config)?;
+    for (saved_head, head) in saved_heads.iter_mut().zip(heads.iter()) {
+        saved_head.name = head.name.clone();
+    }
+    saved_heads.extend(missing_heads);
+
+    if !config.leave_ignored_heads {
+        saved_heads.extend(ignored_heads.iter().map(|h| {
+            let mut h = h.clone();
+            h.config = None;
+            h
+        }));
+    }
+    apply_overrides(&mut saved_heads, config);
+    debug!(heads = ?saved_heads, "Restoring config");
+
+    Ok(SavedConfig {
+        heads: saved_heads,
+        ..saved
+    })
+}
+
+/// Mark the saved head referenced by `default_head` as the primary one,
+/// instead of any marked in the saved heads, before the heads are matched and
+/// their make, model and serial normalized or replaced
+fn resolve_default_head(saved: &mut SavedConfig) {
+    let Some(ref reference) = saved.default_head else {
+        return;
+    };
+    let Some(idx) = saved.heads.iter().position(|h| h.is_wanted(reference)) else {
+        warn!(
+            head = reference,
+            "Default head is not one of the saved heads, ignoring it"
+        );
+        return;
+    };
+    for (i, head) in saved.heads.iter_mut().enumerate() {
+        head.primary = i == idx;
+    }
+}
+
+/// Map saved heads onto the connected heads by position, regardless of make,
+/// model and serial, for testing saved configs on other monitors. This is
+/// unsafe: the saved modes may not be supported by the connected heads.
+///
+/// Saved and connected heads are both sorted by make, model and serial and
+/// paired up in that order, each saved head taking over the identity and
+/// connector name of its connected head. Connected heads left over are
+/// disabled, as are ignored heads; saved heads left over are dropped.
+pub fn force_head_config(
+    saved: SavedConfig,
+    heads: &[Head],
+    ignored_heads: &[Head],
+    config: &ConfigFile,
+) -> SavedConfig {
+    let mut saved = saved;
+    resolve_default_head(&mut saved);
+    let mut saved_heads = saved.heads;
+    normalize_heads(&mut saved_heads, config);
+    saved_heads.sort_by(Head::cmp_mms);
+    if saved_heads.len() != heads.len() {
+        warn!(
+            saved = saved_heads.len(),
+            connected = heads.len(),
+            "Number of saved and connected heads differs"
+        );
+    }
+
+    let mut forced_heads: Vec<Head> = heads
+        .iter()
+        .enumerate()
+        .map(|(idx, head)| match saved_heads.get(idx) {
+            Some(saved_head) => Head {
+                name: head.name.clone(),
+                make: head.make.clone(),
+                model: head.model.clone(),
+                serial: head.serial.clone(),
+                create_if_missing: false,
+                ..saved_head.clone()
+            },
+            None => Head {
+                config: None,
+                ..head.clone()
+            },
+        })
+        .collect();
+    if !config.leave_ignored_heads {
+        forced_heads.extend(ignored_heads.iter().map(|h| Head {
+            config: None,
+            ..h.clone()
+        }));
+    }
+    apply_overrides(&mut forced_heads, config);
+    debug!(heads = ?forced_heads, "Forcing config");
+
+    SavedConfig {
+        heads: forced_heads,
+        ..saved
+    }
+}
+
+/// Reorder runs of saved heads with the same make, model and serial, so that
+/// each is matched to the connected head at the same index, as configured by
+/// `ambiguous_heads`
+///
+/// Both `saved_heads` and `heads` are sorted, and known to have the same make,
+/// model and serial at each index.
+fn match_ambiguous_heads(
+    saved_heads: &mut [Head],
+    heads: &[Head],
+    config: &ConfigFile,
+) -> anyhow::Result<()> {
+    let mut start = 0;
+    while start < heads.len() {
+        let end = start
+            + heads[start..]
+                .iter()
+                .take_while(|h| h.same_mms(&heads[start]))
+                .count();
+        let (saved_run, run) = (&mut saved_heads[start..end], &heads[start..end]);
+        start = end;
+
+        if saved_run.iter().all(|h| h.config == saved_run[0].config) {
+            continue;
+        }
+        let names: Vec<&str> = run
+            .iter()
+            .map(|h| h.name.as_deref().unwrap_or_default())
+            .collect();
+        match config.ambiguous_heads {
+            AmbiguousHeads::Error => {
+                return Err(anyhow::anyhow!(
+                    "Heads {} cannot be told apart, since they have the same make, model and \
+                     serial, but different saved configs; set ambiguous_heads to \"order\" or \
+                     \"geometry\" in the config file to match them anyway",
+                    names.join(", ")
+                ));
+            }
+            AmbiguousHeads::Order => {
+                debug!(heads = ?names, "Matching indistinguishable heads in order");
+            }
+            AmbiguousHeads::Geometry => {
+                debug!(heads = ?names, "Matching indistinguishable heads by position");
+                match_by_position(saved_run, run);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reorder `saved_heads` so that each is matched to the head in `heads` at the
+/// same index whose current position is closest to its saved position,
+/// greedily picking the closest pair first. Disabled heads are matched last.
+fn match_by_position(saved_heads: &mut [Head], heads: &[Head]) {
+    let distance = |saved_head: &Head, head: &Head| match (&saved_head.config, &head.config) {
+        (Some(saved), Some(current)) => {
+            (saved.x as i64 - current.x as i64).pow(2) + (saved.y as i64 - current.y as i64).pow(2)
+        }
+        _ => i64::MAX,
+    };
+
+    let mut pairs: Vec<(i64, usize, usize)> = saved_heads
+        .iter()
+        .enumerate()
+        .flat_map(|(saved_idx, saved_head)| {
+            heads
+                .iter()
+                .enumerate()
+                .map(move |(idx, head)| (distance(saved_head, head), saved_idx, idx))
+        })
+        .collect();
+    pairs.sort();
+
+    let mut order: Vec<Option<usize>> = vec![None; heads.len()];
+    let mut used = vec![false; saved_heads.len()];
+    for (_, saved_idx, idx) in pairs {
+        if order[idx].is_none() && !used[saved_idx] {
+            order[idx] = Some(saved_idx);
+            used[saved_idx] = true;
+        }
+    }
+
+    let reordered: Vec<Head> = order
+        .into_iter()
+        .map(|saved_idx| saved_heads[saved_idx.unwrap()].clone())
+        .collect();
+    saved_heads.clone_from_slice(&reordered);
+}
+
+/// Override settings of saved heads according to the config
+fn apply_overrides(saved_heads: &mut [Head], config: &ConfigFile) {
+    if config.vrr_on_ac.is_none() && config.vrr_on_battery.is_none() {
+        return;
+    }
+
+    let on_battery = power::on_battery();
+    let vrr = if on_battery {
+        config.vrr_on_battery
+    } else {
+        config.vrr_on_ac
+    };
+    if let Some(vrr) = vrr {
+        debug!(on_battery, vrr, "Overriding VRR for power state");
+        for head_config in saved_heads.iter_mut().filter_map(|h| h.config.as_mut()) {
+            head_config.vrr = vrr;
+        }
+    }
+}
+
+/// Keep the connected heads as they are now, except for the saved positions
+/// of those that are enabled both now and in the saved config. Heads are not
+/// enabled or disabled, and settings that cannot be queried, such as extra
+/// commands, are dropped.
+pub fn keep_all_but_positions(saved: &mut SavedConfig, heads: &[Head]) {
+    for saved_head in &mut saved.heads {
+        let Some(current) = heads
+            .iter()
+            .find(|h| h.name.is_some() && h.name == saved_head.name)
+        else {
+            continue;
+        };
+        let mut config = current.config.clone();
+        if let (Some(config), Some(saved_config)) = (&mut config, &saved_head.config) {
+            config.x = saved_config.x;
+            config.y = saved_config.y;
+        }
+        *saved_head = Head {
+            config,
+            ..current.clone()
+        };
+    }
+}
+
+/// Replace the mode of each head to be enabled with the mode it currently
+/// runs, if it is enabled now, keeping the rest of the saved config
+pub fn prefer_current_mode(saved: &mut SavedConfig, heads: &[Head]) {
+    for saved_head in &mut saved.heads {
+        let Some(ref mut saved_config) = saved_head.config else {
+            continue;
+        };
+        let Some(current_config) = heads
+            .iter()
+            .find(|h| h.name.is_some() && h.name == saved_head.name)
+            .and_then(|h| h.config.as_ref())
+        else {
+            continue;
+        };
+
+        debug!(
+            head = saved_head.name.as_deref().unwrap_or_default(),
+            width = current_config.width,
+            height = current_config.height,
+            refresh_rate = current_config.refresh_rate,
+            "Keeping current mode"
+        );
+        saved_config.width = current_config.width;
+        saved_config.height = current_config.height;
+        saved_config.refresh_rate = current_config.refresh_rate;
+        saved_config.custom_mode = current_config.custom_mode.clone();
+        saved_config.mode_spec = None;
+    }
+}
+
+/// Multiply the scale of each head to be enabled by `factor`
+///
+/// Scales are rounded to multiples of 1/120, as used by the fractional scale
+/// protocol, and kept between 0.25 and 10.
+pub fn scale_heads(saved: &mut SavedConfig, factor: f64) {
+    for saved_head in &mut saved.heads {
+        let Some(ref mut saved_config) = saved_head.config else {
+            continue;
+        };
+
+        let scale = ((saved_config.scale * factor * 120.0).round() / 120.0).clamp(0.25, 10.0);
+        info!(
+            head = saved_head.name.as_deref().unwrap_or_default(),
+            saved = saved_config.scale,
+            scale,
+            "Scaling head"
+        );
+        saved_config.scale = scale;
+    }
+}
+
+/// Load the saved config of `profile`, or if that fails, of the first of the
+/// `fallback_profiles` from the config that can be loaded. Returns the name of
+/// the profile that was loaded along with its config.
+pub fn load_head_config_with_fallbacks(
+    heads: &[Head],
+    ignored_heads: &[Head],
+    profile: &str,
+    config: &ConfigFile,
+) -> anyhow::Result<(String, SavedConfig)> {
+    let err = match load_head_config(heads, ignored_heads, profile, config) {
+        Ok(saved) => {
+            info!(profile, "Using saved config of profile");
+            return Ok((profile.to_string(), saved));
+        }
+        Err(err) => err,
+    };
+
+    for fallback_profile in config.fallback_profiles.iter() {
+        match load_head_config(heads, ignored_heads, fallback_profile, config) {
+            Ok(saved) => {
+                info!(
+                    profile = fallback_profile,
+                    requested = profile,
+                    "Requested profile not available, using fallback profile"
+                );
+                return Ok((fallback_profile.clone(), saved));
+            }
+            Err(err) => debug!(profile = fallback_profile, %err, "Fallback profile not available"),
+        }
+    }
+
+    Err(err)
+}
+
+/// Apply saved heads, as returned by `load_head_config`, to the connected
+/// `heads`
+///
+/// Heads with `max_refresh` get the highest refresh rate available for their
+/// saved resolution, unless they have a custom mode.
+///
+/// Heads that are currently disabled, but enabled in the saved config, are
+/// checked to support the saved mode first, since it cannot have been
+/// validated against the live config. With `mode_degradation`, all heads are
+/// checked, falling back to other modes as configured. Custom modes are not
+/// checked.
+///
+/// Saved heads with `create_if_missing` that are not connected are created
+/// first, or skipped if the backend cannot create them.
+///
+/// Fails without applying anything if a head is no longer connected by the
+/// time the config is applied (see `check_head_names`).
+///
+/// A warning is logged if the config was saved with a different version of the
+/// compositor, whose configuration syntax may have changed since.
+///
+/// Afterwards, workspaces are bound to heads as given by `workspace_map`.
+pub fn apply_head_config(
+    backend: &dyn Backend,
+    saved: &SavedConfig,
+    heads: &[Head],
+    config: &ConfigFile,
+) -> anyhow::Result<()> {
+    if let Some(ref saved_compositor) = saved.compositor {
+        match backend.compositor() {
+            Ok(Some(compositor)) if compositor != *saved_compositor => warn!(
+                saved = %saved_compositor,
+                current = %compositor,
+                "Config was saved with a different compositor version, the syntax may have changed"
+            ),
+            Ok(_) => (),
+            Err(err) => debug!(%err, "Failed to query compositor version"),
+        }
+    }
+
+    let capabilities = backend.capabilities();
+    for saved_head in saved.heads.iter().filter(|h| h.name.is_some()) {
+        for field in capabilities.unsupported(saved_head, &saved.heads) {
+            warn!(
+                head = saved_head.name.as_deref().unwrap_or_default(),
+                field, "Backend does not support this setting, ignoring it"
+            );
+        }
+    }
+    if saved.heads.iter().filter(|h| h.primary).count() > 1 {
+        warn!("More than one head is primary, using the first one");
+    }
+
+    let mut saved_heads = saved.heads.clone();
+    saved_heads.retain(|saved_head| {
+        let Some(ref name) = saved_head.name else {
+            return true;
+        };
+        if !saved_head.create_if_missing || heads.iter().any(|h| h.name == saved_head.name) {
+            return true;
+        }
+        if !capabilities.create_if_missing {
+            return false;
+        }
+        if name.is_empty() {
+            warn!("Cannot create head without connector name, skipping it");
+            return false;
+        }
+        match backend.create_head(name) {
+            Ok(()) => {
+                info!(head = name, "Created missing head");
+                true
+            }
+            Err(err) => {
+                warn!(
+                    head = name,
+                    "Cannot create missing head, skipping it: {:#}", err
+                );
+                false
+            }
+        }
+    });
+
+    resolve_mirror_groups(&mut saved_heads)?;
+    resolve_mode_specs(backend, &mut saved_heads)?;
+
+    if saved_heads
+        .iter()
+        .any(|h| h.config.as_ref().is_some_and(|c| c.max_refresh))
+    {
+        let available_modes = backend.get_available_modes()?;
+        for saved_head in saved_heads.iter_mut() {
+            let name = saved_head.name.as_deref().unwrap_or_default();
+            let Some(ref mut head_config) = saved_head.config else {
+                continue;
+            };
+            if !head_config.max_refresh || head_config.custom_mode.is_some() {
+                continue;
+            }
+            let Some(refresh_rate) = available_modes
+                .get(name)
+                .into_iter()
+                .flatten()
+                .filter(|m| {
+                    m.mode.width == head_config.width && m.mode.height == head_config.height
+                })
+                .map(|m| m.mode.refresh_rate)
+                .max_by(f64::total_cmp)
+            else {
+                warn!(
+                    head = name,
+                    "No modes available for saved resolution, keeping refresh rate"
+                );
+                continue;
+            };
+            debug!(head = name, refresh_rate, "Using highest refresh rate");
+            head_config.refresh_rate = refresh_rate;
+        }
+    }
+
+    let is_enabling = |saved_head: &Head| {
+        heads
+            .iter()
+            .any(|h| h.name == saved_head.name && h.config.is_none())
+    };
+    let degrading = config.mode_degradation != ModeDegradation::None;
+    let checked: Vec<usize> = saved_heads
+        .iter()
+        .enumerate()
+        .filter(|(_, saved_head)| {
+            saved_head
+                .config
+                .as_ref()
+                .is_some_and(|c| c.custom_mode.is_none())
+                && (degrading || is_enabling(saved_head))
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+
+    if !checked.is_empty() {
+        let available_modes = backend.get_available_modes()?;
+        for idx in checked {
+            let enabling = is_enabling(&saved_heads[idx]);
+            let saved_head = &mut saved_heads[idx];
+            let name = saved_head.name.as_deref().unwrap_or_default();
+            let head_config = saved_head.config.as_mut().unwrap();
+            let mode = head_config.mode();
+            let modes = available_modes
+                .get(name)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            if modes.iter().any(|m| m.mode.matches(&mode)) {
+                if enabling {
+                    debug!(head = name, %mode, "Enabling disabled head with saved mode");
+                }
+            } else if let Some(degraded) = degrade_mode(&mode, modes, config.mode_degradation) {
+                warn!(head = name, saved = %mode, mode = %degraded, "Saved mode is not available, falling back");
+                head_config.width = degraded.width;
+                head_config.height = degraded.height;
+                head_config.refresh_rate = degraded.refresh_rate;
+            } else if enabling {
+                return Err(anyhow::anyhow!(
+                    "Saved mode {} is not available for head {}",
+                    mode,
+                    name
+                ));
+            }
+        }
+    }
+
+    check_head_names(backend, &saved_heads)?;
+    backend.set_head_config(&saved_heads)?;
+    // Focus the primary head only once all heads are configured
+    if let Some(primary) = saved_heads.iter().find(|h| h.primary && h.config.is_some()) {
+        backend.focus_head(primary)?;
+    }
+    apply_workspace_map(backend, &saved_heads, config)
+}
+
+/// Check that every head about to be configured is still connected under its
+/// name, querying the backend again once if not. Heads that have just been
+/// created are not checked. Connector names can change
+/// (e.g. after a GPU reset) between querying the heads and applying a config,
+/// and configuring a name that no longer exists silently does nothing.
+fn check_head_names(backend: &dyn Backend, heads: &[Head]) -> anyhow::Result<()> {
+    let stale_names = || -> anyhow::Result<Vec<String>> {
+        let live_heads = backend.get_all_heads()?;
+        Ok(heads
+            .iter()
+            .filter(|h| !h.create_if_missing)
+            .filter_map(|h| h.name.clone())
+            .filter(|name| !live_heads.iter().any(|h| h.name.as_ref() == Some(name)))
+            .collect())
+    };
+
+    let stale = stale_names()?;
+    if stale.is_empty() {
+        return Ok(());
+    }
+    debug!(?stale, "Heads not connected, querying again");
+    let stale = stale_names()?;
+    if !stale.is_empty() {
+        return Err(anyhow::anyhow!(
+            "not applying config, heads are no longer connected: {}",
+            stale.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Give the enabled heads with a `mode_spec` the mode it stands for among the
+/// modes available for them. Fails if there is no such mode, or if a head has
+/// neither a mode spec nor a resolution.
+fn resolve_mode_specs(backend: &dyn Backend, heads: &mut [Head]) -> anyhow::Result<()> {
+    let mut available_modes = None;
+    for head in heads.iter_mut() {
+        let name = head.name.as_deref().unwrap_or_default();
+        let Some(ref mut head_config) = head.config else {
+            continue;
+        };
+        let Some(spec) = head_config.mode_spec.take() else {
+            if head_config.custom_mode.is_none()
+                && (head_config.width <= 0 || head_config.height <= 0)
+            {
+                return Err(anyhow::anyhow!("no mode saved for head {}", name));
+            }
+            continue;
+        };
+        let mode = match spec {
+            ModeSpec::Exact(mode) => mode,
+            spec => {
+                if available_modes.is_none() {
+                    available_modes = Some(backend.get_available_modes()?);
+                }
+                let modes = available_modes
+                    .as_ref()
+                    .and_then(|modes| modes.get(name))
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let Some(mode) = resolve_mode_spec(&spec, modes) else {
+                    return Err(anyhow::anyhow!(
+                        "no mode available for head {} to resolve {:?}",
+                        name,
+                        String::from(spec)
+                    ));
+                };
+                debug!(head = name, mode = %mode, "Resolved mode");
+                mode
+            }
+        };
+        head_config.width = mode.width;
+        head_config.height = mode.height;
+        head_config.refresh_rate = mode.refresh_rate;
+    }
+    Ok(())
+}
+
+/// The mode `spec` stands for among the `modes` available for a head
+fn resolve_mode_spec(spec: &ModeSpec, modes: &[AvailableMode]) -> Option<HeadMode> {
+    let mode = match spec {
+        ModeSpec::Exact(mode) => return Some(mode.clone()),
+        ModeSpec::Native => ModePolicy::HighestRes.pick(modes),
+        ModeSpec::MaxRefresh => ModePolicy::HighestRefresh.pick(modes),
+        ModeSpec::Preferred => modes.iter().find(|m| m.preferred),
+    };
+    mode.map(|m| m.mode.clone())
+}
+
+/// Make the enabled heads of each mirror group mirror its source, failing if a
+/// group does not have exactly one source, or the source is not enabled
+fn resolve_mirror_groups(heads: &mut [Head]) -> anyhow::Result<()> {
+    let mut groups: Vec<&str> = heads
+        .iter()
+        .filter_map(|h| h.mirror_group.as_deref())
+        .collect();
+    groups.sort();
+    groups.dedup();
+    let groups: Vec<String> = groups.into_iter().map(str::to_string).collect();
+
+    for group in groups {
+        let in_group = |h: &Head| h.mirror_group.as_ref() == Some(&group);
+        let sources: Vec<&Head> = heads
+            .iter()
+            .filter(|h| in_group(h) && h.mirror_source)
+            .collect();
+        let [source] = sources[..] else {
+            return Err(anyhow::anyhow!(
+                "mirror group {} has {} sources, but needs exactly one",
+                group,
+                sources.len()
+            ));
+        };
+        let Some(source_name) = source.name.clone().filter(|_| source.config.is_some()) else {
+            return Err(anyhow::anyhow!(
+                "source of mirror group {} is not enabled",
+                group
+            ));
+        };
+
+        for head in heads.iter_mut().filter(|h| in_group(h) && !h.mirror_source) {
+            if let Some(ref mut head_config) = head.config {
+                debug!(
+                    head = head.name.as_deref().unwrap_or_default(),
+                    source = source_name,
+                    group,
+                    "Mirroring head"
+                );
+                head_config.mirror_of = Some(source_name.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Bind the workspaces in `workspace_map` to the heads of `saved_heads` that
+/// are enabled. Entries for other heads are skipped.
+fn apply_workspace_map(
+    backend: &dyn Backend,
+    saved_heads: &[Head],
+    config: &ConfigFile,
+) -> anyhow::Result<()> {
+    if config.workspace_map.is_empty() {
+        return Ok(());
+    }
+    if !backend.capabilities().workspaces {
+        warn!("Backend does not support workspaces, ignoring workspace_map");
+        return Ok(());
+    }
+
+    let mut workspaces = Vec::new();
+    for map in &config.workspace_map {
+        let Some(name) = saved_heads
+            .iter()
+            .filter(|h| h.config.is_some())
+            .find(|h| h.is_wanted(&map.head))
+            .and_then(|h| h.name.clone())
+        else {
+            debug!(head = map.head, "Head of workspace map is not enabled");
+            continue;
+        };
+        workspaces.extend(map.workspaces.iter().map(|workspace| WorkspaceAssignment {
+            workspace: workspace.to_string(),
+            head: name.clone(),
+        }));
+    }
+
+    if workspaces.is_empty() {
+        return Ok(());
+    }
+    backend.bind_workspaces(&workspaces)
+}
+
+/// A mode to fall back to from `mode`, which is not among `modes`, going only
+/// as far as `degradation` allows
+fn degrade_mode(
+    mode: &HeadMode,
+    modes: &[AvailableMode],
+    degradation: ModeDegradation,
+) -> Option<HeadMode> {
+    let pixels = |m: &HeadMode| m.width as i64 * m.height as i64;
+
+    let same_resolution = || {
+        modes
+            .iter()
+            .map(|m| &m.mode)
+            .filter(|m| {
+                m.width == mode.width
+                    && m.height == mode.height
+                    && m.refresh_rate < mode.refresh_rate
+            })
+            .max_by(|a, b| a.refresh_rate.total_cmp(&b.refresh_rate))
+    };
+    let same_aspect = || {
+        modes
+            .iter()
+            .map(|m| &m.mode)
+            .filter(|m| {
+                m.width as i64 * mode.height as i64 == m.height as i64 * mode.width as i64
+                    && pixels(m) < pixels(mode)
+            })
+            .max_by(|a, b| {
+                pixels(a)
+                    .cmp(&pixels(b))
+                    .then(a.refresh_rate.total_cmp(&b.refresh_rate))
+            })
+    };
+    let preferred = || modes.iter().find(|m| m.preferred).map(|m| &m.mode);
+
+    if degradation >= ModeDegradation::SameResolution
+        && let Some(m) = same_resolution()
+    {
+        return Some(m.clone());
+    }
+    if degradation >= ModeDegradation::SameAspect
+        && let Some(m) = same_aspect()
+    {
+        return Some(m.clone());
+    }
+    if degradation >= ModeDegradation::Preferred {
+        return preferred().cloned();
+    }
+    None
+}
+
+/// Move workspaces back to the heads they were on, as returned by
+/// `Backend::get_workspaces` before applying a config
+///
+/// Workspaces whose head is no longer enabled are left where they are.
+pub fn restore_workspaces(
+    backend: &dyn Backend,
+    workspaces: &[WorkspaceAssignment],
+) -> anyhow::Result<()> {
+    let enabled: Vec<String> = backend
+        .get_all_heads()?
+        .into_iter()
+        .filter(|h| h.config.is_some())
+        .filter_map(|h| h.name)
+        .collect();
+
+    let (movable, stranded): (Vec<WorkspaceAssignment>, Vec<WorkspaceAssignment>) = workspaces
+        .iter()
+        .cloned()
+        .partition(|w| enabled.contains(&w.head));
+    for assignment in stranded {
+        debug!(
+            workspace = assignment.workspace,
+            head = assignment.head,
+            "Head of workspace is no longer enabled"
+        );
+    }
+
+    if movable.is_empty() {
+        return Ok(());
+    }
+    backend.move_workspaces(&movable)
+}
+
+/// Run the hooks of a saved config after it has been applied as `profile`
+///
+/// `{profile}` in a hook is replaced with the profile name and `{head_count}`
+/// with the number of enabled heads. Hooks are run with `sh -c`, and failures
+/// are logged, but otherwise ignored.
+pub fn run_hooks(saved: &SavedConfig, profile: &str) {
+    let head_count = saved.heads.iter().filter(|h| h.config.is_some()).count();
+    for hook in &saved.hooks {
+        let command = hook
+            .replace("{profile}", profile)
+            .replace("{head_count}", &head_count.to_string());
+        debug!(command, "Running hook");
+        match std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .status()
+        {
+            Ok(status) if status.success() => (),
+            Ok(status) => warn!(command, %status, "Hook failed"),
+            Err(err) => warn!(command, %err, "Failed to run hook"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::backend::Capabilities;
+    use crate::config::IdentityNormalization;
+    use crate::types::tests::{head, head_config};
+    use crate::types::{AvailableMode, HeadConfig, HeadMode, Transform};
+
+    /// Reports the given heads and modes, and records the heads it is asked
+    /// to configure
+    #[derive(Default)]
+    struct FakeBackend {
+        heads: Vec<Head>,
+        modes: HashMap<String, Vec<AvailableMode>>,
+        queries: AtomicUsize,
+        applied: Mutex<Option<Vec<Head>>>,
+    }
+
+    impl Backend for FakeBackend {
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
+
+        fn get_all_heads(&self) -> anyhow::Result<Vec<Head>> {
+            self.queries.fetch_add(1, Ordering::Relaxed);
+            Ok(self.heads.clone())
+        }
+
+        fn get_available_modes(&self) -> anyhow::Result<HashMap<String, Vec<AvailableMode>>> {
+            Ok(self.modes.clone())
+        }
+
+        fn set_head_config(&self, heads: &[Head]) -> anyhow::Result<()> {
+            *self.applied.lock().unwrap() = Some(heads.to_vec());
+            Ok(())
+        }
+
+        fn fallback_head_config(
+            &self,
+            _active_head_names: &[String],
+            _inactive_head_names: &[String],
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A head without make, model and serial, e.g. a headless output
+    fn anonymous_head(name: &str, x: i32, scale: f64) -> Head {
+        Head {
+            make: String::new(),
+            model: String::new(),
+            ..head(
+                name,
+                "",
+                Some(HeadConfig {
+                    x,
+                    ..head_config(Transform::Normal, scale)
+                }),
+            )
+        }
+    }
+
+    fn scales(heads: &[Head]) -> Vec<f64> {
+        heads
+            .iter()
+            .map(|h| h.config.as_ref().unwrap().scale)
+            .collect()
+    }
+
+    #[test]
+    fn match_by_position_swaps_heads() {
+        let mut saved_heads = [anonymous_head("", 2256, 1.0), anonymous_head("", 0, 2.0)];
+        let heads = [
+            anonymous_head("HEADLESS-1", 0, 1.0),
+            anonymous_head("HEADLESS-2", 2256, 1.0),
+        ];
+        match_by_position(&mut saved_heads, &heads);
+        assert_eq!(scales(&saved_heads), [2.0, 1.0]);
+    }
+
+    #[test]
+    fn match_by_position_without_unique_pairing() {
+        // Both saved heads are as far from either connected head, so any
+        // pairing is as good, but each saved head must be used once
+        let mut saved_heads = [anonymous_head("", 1128, 1.0), anonymous_head("", 1128, 2.0)];
+        let heads = [
+            anonymous_head("HEADLESS-1", 0, 1.0),
+            anonymous_head("HEADLESS-2", 2256, 1.0),
+        ];
+        match_by_position(&mut saved_heads, &heads);
+        let mut matched = scales(&saved_heads);
+        matched.sort_by(f64::total_cmp);
+        assert_eq!(matched, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn check_head_names_reports_missing_head() {
+        let config = head_config(Transform::Normal, 1.0);
+        let backend = FakeBackend {
+            heads: vec![head("DP-1", "1", Some(config.clone()))],
+            ..Default::default()
+        };
+        let saved_heads = [
+            head("DP-1", "1", Some(config.clone())),
+            head("DP-9", "9", Some(config)),
+        ];
+        let err = check_head_names(&backend, &saved_heads).unwrap_err();
+        assert!(err.to_string().contains("DP-9"), "{}", err);
+        assert!(!err.to_string().contains("DP-1"), "{}", err);
+        // The heads are queried again before giving up
+        assert_eq!(backend.queries.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn check_head_names_skips_created_heads() {
+        let config = head_config(Transform::Normal, 1.0);
+        let backend = FakeBackend {
+            heads: vec![head("DP-1", "1", Some(config.clone()))],
+            ..Default::default()
+        };
+        let saved_heads = [
+            head("DP-1", "1", Some(config.clone())),
+            Head {
+                create_if_missing: true,
+                ..head("HEADLESS-1", "HEADLESS-1", Some(config))
+            },
+        ];
+        check_head_names(&backend, &saved_heads).unwrap();
+        assert_eq!(backend.queries.load(Ordering::Relaxed), 1);
+    }
+
+    /// Config of a DP-1 head saved while the lid was open, and the heads
+    /// connected now that the lid is closed, with eDP-1 ignored
+    fn lid_closed() -> (SavedConfig, Vec<Head>, Vec<Head>) {
+        let config = head_config(Transform::Normal, 1.0);
+        let saved = SavedConfig {
+            heads: vec![Head {
+                name: None,
+                ..head("DP-1", "1", Some(config.clone()))
+            }],
+            ..Default::default()
+        };
+        let heads = vec![head("DP-1", "1", Some(config.clone()))];
+        let ignored_heads = vec![Head {
+            make: "BOE".to_string(),
+            model: "0x095F".to_string(),
+            ..head("eDP-1", "", Some(config))
+        }];
+        (saved, heads, ignored_heads)
+    }
+
+    fn configs(saved: &SavedConfig) -> Vec<(&str, bool)> {
+        saved
+            .heads
+            .iter()
+            .map(|h| (h.name.as_deref().unwrap(), h.config.is_some()))
+            .collect()
+    }
+
+    #[test]
+    fn ignored_heads_are_disabled() {
+        let config = ConfigFile::default();
+        let (saved, heads, ignored_heads) = lid_closed();
+        let matched =
+            match_head_config(saved.clone(), &heads, &ignored_heads, &config, &"test").unwrap();
+        assert_eq!(configs(&matched), [("DP-1", true), ("eDP-1", false)]);
+        let forced = force_head_config(saved, &heads, &ignored_heads, &config);
+        assert_eq!(configs(&forced), [("DP-1", true), ("eDP-1", false)]);
+    }
+
+    #[test]
+    fn ignored_heads_are_left_alone() {
+        let config = ConfigFile {
+            leave_ignored_heads: true,
+            ..Default::default()
+        };
+        let (saved, heads, ignored_heads) = lid_closed();
+        let matched =
+            match_head_config(saved.clone(), &heads, &ignored_heads, &config, &"test").unwrap();
+        assert_eq!(configs(&matched), [("DP-1", true)]);
+        let forced = force_head_config(saved, &heads, &ignored_heads, &config);
+        assert_eq!(configs(&forced), [("DP-1", true)]);
+    }
+
+    /// DP-1 connected but turned off, offering the given modes, and a config
+    /// saved while it was on
+    fn disabled_head(modes: &[(i32, i32, f64)]) -> (FakeBackend, SavedConfig) {
+        let modes = modes
+            .iter()
+            .map(|&(width, height, refresh_rate)| AvailableMode {
+                mode: HeadMode {
+                    width,
+                    height,
+                    refresh_rate,
+                },
+                current: false,
+                preferred: false,
+            })
+            .collect();
+        let backend = FakeBackend {
+            heads: vec![head("DP-1", "1", None)],
+            modes: HashMap::from([("DP-1".to_string(), modes)]),
+            ..Default::default()
+        };
+        let saved = SavedConfig {
+            heads: vec![head("DP-1", "1", Some(head_config(Transform::Normal, 1.0)))],
+            ..Default::default()
+        };
+        (backend, saved)
+    }
+
+    #[test]
+    fn disabled_head_is_enabled_with_saved_mode() {
+        let config = ConfigFile::default();
+        let (backend, saved) = disabled_head(&[(3840, 2160, 60.0), (2256, 1504, 60.0)]);
+        let (heads, _) = current_heads(&backend, &config).unwrap();
+        apply_head_config(&backend, &saved, &heads, &config).unwrap();
+        let applied = backend.applied.lock().unwrap().clone().unwrap();
+        assert_eq!(applied[0].config, saved.heads[0].config);
+    }
+
+    #[test]
+    fn disabled_head_is_not_enabled_with_unavailable_mode() {
+        let config = ConfigFile::default();
+        let (backend, saved) = disabled_head(&[(3840, 2160, 60.0)]);
+        let (heads, _) = current_heads(&backend, &config).unwrap();
+        let err = apply_head_config(&backend, &saved, &heads, &config).unwrap_err();
+        assert!(err.to_string().contains("2256x1504"), "{}", err);
+        assert!(backend.applied.lock().unwrap().is_none());
+    }
+
+    fn headless_backend(names: &[&str]) -> FakeBackend {
+        let heads = names
+            .iter()
+            .map(|name| Head {
+                make: String::new(),
+                model: String::new(),
+                ..head(name, "", Some(head_config(Transform::Normal, 1.0)))
+            })
+            .chain([head("DP-1", "1", Some(head_config(Transform::Normal, 1.0)))])
+            .collect();
+        FakeBackend {
+            heads,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn headless_heads_identified_by_connector_name() {
+        let config = ConfigFile {
+            connector_name_fallback: true,
+            ..Default::default()
+        };
+        let (heads, _) = current_heads(&headless_backend(&["HEADLESS-2"]), &config).unwrap();
+        let serials: Vec<&str> = heads.iter().map(|h| h.serial.as_str()).collect();
+        assert_eq!(serials, ["HEADLESS-2", "1"]);
+
+        let (other_heads, _) = current_heads(&headless_backend(&["HEADLESS-3"]), &config).unwrap();
+        assert_ne!(
+            layout_identity(&heads, &config),
+            layout_identity(&other_heads, &config)
+        );
+    }
+
+    #[test]
+    fn headless_heads_without_connector_name_fallback() {
+        let config = ConfigFile::default();
+        let (heads, _) = current_heads(&headless_backend(&["HEADLESS-2"]), &config).unwrap();
+        let serials: Vec<&str> = heads.iter().map(|h| h.serial.as_str()).collect();
+        assert_eq!(serials, ["", "1"]);
+
+        let (other_heads, _) = current_heads(&headless_backend(&["HEADLESS-3"]), &config).unwrap();
+        assert_eq!(
+            layout_identity(&heads, &config),
+            layout_identity(&other_heads, &config)
+        );
+    }
+
+    #[test]
+    fn headless_head_is_matched_by_connector_name() {
+        let config = ConfigFile {
+            connector_name_fallback: true,
+            ..Default::default()
+        };
+        let (heads, ignored_heads) =
+            current_heads(&headless_backend(&["HEADLESS-2"]), &config).unwrap();
+        // Connector names are not saved
+        let saved = SavedConfig {
+            heads: heads
+                .iter()
+                .map(|h| Head {
+                    name: None,
+                    ..h.clone()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(saved.heads[0].serial, "HEADLESS-2");
+
+        let matched =
+            match_head_config(saved.clone(), &heads, &ignored_heads, &config, &"test").unwrap();
+        let names: Vec<&str> = matched
+            .heads
+            .iter()
+            .map(|h| h.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["HEADLESS-2", "DP-1"]);
+
+        // Saved for HEADLESS-2, so not for HEADLESS-3
+        let (other_heads, _) = current_heads(&headless_backend(&["HEADLESS-3"]), &config).unwrap();
+        assert!(match_head_config(saved, &other_heads, &[], &config, &"test").is_err());
+    }
+
+    fn dell(make: &str) -> Head {
+        Head {
+            make: make.to_string(),
+            ..head("DP-1", "ABC123", Some(head_config(Transform::Normal, 1.0)))
+        }
+    }
+
+    #[test]
+    fn identity_normalization_trims() {
+        let config = ConfigFile {
+            identity_normalization: IdentityNormalization::Trim,
+            ..Default::default()
+        };
+        assert_eq!(
+            layout_identity(&[dell("Dell Inc. ")], &config),
+            layout_identity(&[dell("Dell Inc.")], &config)
+        );
+        assert_ne!(
+            layout_identity(&[dell("DELL INC.")], &config),
+            layout_identity(&[dell("Dell Inc.")], &config)
+        );
+
+        let config = ConfigFile::default();
+        assert_ne!(
+            layout_identity(&[dell("Dell Inc. ")], &config),
+            layout_identity(&[dell("Dell Inc.")], &config)
+        );
+    }
+
+    #[test]
+    fn identity_normalization_lowercases() {
+        let config = ConfigFile {
+            identity_normalization: IdentityNormalization::TrimLowercase,
+            ..Default::default()
+        };
+        assert_eq!(
+            layout_identity(&[dell(" DELL INC.")], &config),
+            layout_identity(&[dell("Dell Inc.")], &config)
+        );
+    }
+
+    #[test]
+    fn builtin_aliases_apply_before_normalization() {
+        let config = ConfigFile {
+            builtin_aliases: true,
+            identity_normalization: IdentityNormalization::Trim,
+            ..Default::default()
+        };
+        assert_eq!(
+            layout_identity(&[dell("DEL")], &config),
+            layout_identity(&[dell("Dell Inc. ")], &config)
+        );
+
+        let config = ConfigFile::default();
+        assert_ne!(
+            layout_identity(&[dell("DEL")], &config),
+            layout_identity(&[dell("Dell Inc.")], &config)
+        );
+    }
+
+    #[test]
+    fn normalized_heads_match_saved_config() {
+        let config = ConfigFile {
+            identity_normalization: IdentityNormalization::Trim,
+            ..Default::default()
+        };
+        let backend = FakeBackend {
+            heads: vec![dell("Dell Inc. ")],
+            ..Default::default()
+        };
+        let (heads, ignored_heads) = current_heads(&backend, &config).unwrap();
+        let saved = SavedConfig {
+            heads: vec![Head {
+                name: None,
+                ..dell("Dell Inc.")
+            }],
+            ..Default::default()
+        };
+        let matched = match_head_config(saved, &heads, &ignored_heads, &config, &"test").unwrap();
+        assert_eq!(matched.heads[0].name.as_deref(), Some("DP-1"));
+    }
+
+    #[test]
+    fn resave_keeps_mode_spec_only_while_in_its_mode() {
+        let config = ConfigFile::default();
+        let mode = |width, height, preferred| AvailableMode {
+            mode: HeadMode {
+                width,
+                height,
+                refresh_rate: 60.0,
+            },
+            current: false,
+            preferred,
+        };
+        let backend = FakeBackend {
+            heads: vec![head("DP-1", "1", Some(head_config(Transform::Normal, 1.0)))],
+            modes: HashMap::from([(
+                "DP-1".to_string(),
+                vec![mode(3840, 2160, false), mode(2256, 1504, true)],
+            )]),
+            ..Default::default()
+        };
+        let (heads, _) = current_heads(&backend, &config).unwrap();
+        let previous = |mode_spec| SavedConfig {
+            heads: vec![head(
+                "DP-1",
+                "1",
+                Some(HeadConfig {
+                    mode_spec: Some(mode_spec),
+                    ..head_config(Transform::Normal, 1.0)
+                }),
+            )],
+            ..Default::default()
+        };
+        let mode_spec = |previous| {
+            saved_config(&backend, &heads, Some(previous), &config).heads[0]
+                .config
+                .as_ref()
+                .unwrap()
+                .mode_spec
+                .clone()
+        };
+
+        // Still in the preferred mode
+        assert_eq!(
+            mode_spec(previous(ModeSpec::Preferred)),
+            Some(ModeSpec::Preferred)
+        );
+        // Changed to 2256x1504 since saved as native
+        assert_eq!(mode_spec(previous(ModeSpec::Native)), None);
+    }
+}
@@ -0,0 +1,270 @@
+use serde_json::Value;
+
+use crate::types::SavedConfig;
+
+/// JSON Schema of saved screen config files
+pub const PROFILE_SCHEMA: &str = include_str!("profile.schema.json");
+
+/// A problem found in a saved screen config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    /// JSON pointer to the offending value, e.g. `/heads/0/config/scale`
+    pub pointer: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+/// Check the contents of a saved screen config file against
+/// `PROFILE_SCHEMA`. Syntax errors are reported with line and column. An
+/// empty list means the file is valid.
+pub fn validate_profile(contents: &[u8]) -> Vec<ValidationError> {
+    let instance: Value = match serde_json::from_slice(contents) {
+        Ok(instance) => instance,
+        Err(err) => {
+            return vec![ValidationError {
+                pointer: String::new(),
+                message: err.to_string(),
+            }];
+        }
+    };
+    let schema: Value = serde_json::from_str(PROFILE_SCHEMA).expect("schema is valid JSON");
+
+    let mut errors = Vec::new();
+    Validator {
+        root: &schema,
+        errors: &mut errors,
+    }
+    .validate(&schema, &instance, String::new());
+
+    // Anything the schema cannot express is still caught when parsing
+    if errors.is_empty()
+        && let Err(err) = SavedConfig::from_slice(contents)
+    {
+        errors.push(ValidationError {
+            pointer: String::new(),
+            message: err.to_string(),
+        });
+    }
+    errors
+}
+
+/// Checks instances against the subset of JSON Schema used by
+/// `PROFILE_SCHEMA`
+struct Validator<'a> {
+    root: &'a Value,
+    errors: &'a mut Vec<ValidationError>,
+}
+
+impl<'a> Validator<'a> {
+    fn error(&mut self, pointer: &str, message: String) {
+        self.errors.push(ValidationError {
+            pointer: pointer.to_string(),
+            message,
+        });
+    }
+
+    /// The schema `schema` refers to with `$ref`, or `schema` itself
+    fn resolve(&self, schema: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+                .unwrap_or_else(|| panic!("unresolvable reference {} in schema", reference)),
+            None => schema,
+        }
+    }
+
+    fn validate(&mut self, schema: &'a Value, instance: &Value, pointer: String) {
+        let schema = self.resolve(schema);
+
+        if let Some(branches) = schema.get("oneOf").and_then(Value::as_array) {
+            // The branches of the schema differ in type, so only the one of
+            // the instance's type is checked, which gives clearer errors
+            match branches
+                .iter()
+                .find(|branch| type_matches(self.resolve(branch), instance))
+            {
+                Some(branch) => self.validate(branch, instance, pointer.clone()),
+                None => self.error(
+                    &pointer,
+                    format!(
+                        "expected a list of heads or an object, got {}",
+                        type_name(instance)
+                    ),
+                ),
+            }
+        }
+
+        if !type_matches(schema, instance) {
+            let expected = match &schema["type"] {
+                Value::Array(types) => types
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                other => other.as_str().unwrap_or_default().to_string(),
+            };
+            self.error(
+                &pointer,
+                format!("expected {}, got {}", expected, type_name(instance)),
+            );
+            return;
+        }
+
+        if let Some(number) = instance.as_f64() {
+            if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64)
+                && number < minimum
+            {
+                self.error(&pointer, format!("{} is less than {}", number, minimum));
+            }
+            if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64)
+                && number > maximum
+            {
+                self.error(&pointer, format!("{} is greater than {}", number, maximum));
+            }
+        }
+
+        if let Some(items) = instance.as_array() {
+            if let Some(min_items) = schema.get("minItems").and_then(Value::as_u64)
+                && (items.len() as u64) < min_items
+            {
+                self.error(
+                    &pointer,
+                    format!("expected at least {} items, got {}", min_items, items.len()),
+                );
+            }
+            if let Some(max_items) = schema.get("maxItems").and_then(Value::as_u64)
+                && (items.len() as u64) > max_items
+            {
+                self.error(
+                    &pointer,
+                    format!("expected at most {} items, got {}", max_items, items.len()),
+                );
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (idx, item) in items.iter().enumerate() {
+                    self.validate(item_schema, item, format!("{}/{}", pointer, idx));
+                }
+            }
+        }
+
+        if let Some(object) = instance.as_object() {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !object.contains_key(required) {
+                    self.error(&pointer, format!("missing field {:?}", required));
+                }
+            }
+            for (key, value) in object {
+                let child = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property_schema) => self.validate(property_schema, value, child),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        self.error(&child, "unknown field".to_string())
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+}
+
+/// Whether `instance` is of the type required by `schema`, if any
+fn type_matches(schema: &Value, instance: &Value) -> bool {
+    let matches = |name: &str| match name {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "integer" => instance.is_i64() || instance.is_u64(),
+        "number" => instance.is_number(),
+        "boolean" => instance.is_boolean(),
+        "null" => instance.is_null(),
+        _ => false,
+    };
+    match schema.get("type") {
+        Some(Value::String(name)) => matches(name),
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).any(matches),
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Compositor, Head, HeadConfig, ModeSpec, Transform};
+
+    #[test]
+    fn populated_config_matches_schema() {
+        let head = Head {
+            name: Some("DP-1".to_string()),
+            make: "Dell Inc.".to_string(),
+            model: "DELL U2720Q".to_string(),
+            serial: "ABC123".to_string(),
+            config: Some(HeadConfig {
+                width: 3840,
+                height: 2160,
+                refresh_rate: 60.0,
+                x: 1504,
+                y: 0,
+                scale: 1.5,
+                transform: Transform::Rotate90,
+                vrr: true,
+                custom_mode: Some("3840x2160@60".to_string()),
+                bitdepth: Some(10),
+                cm: Some("hdr".to_string()),
+                sdr_brightness: Some(1.2),
+                sdr_saturation: Some(0.9),
+                tearing: Some(true),
+                mirror_of: Some("eDP-1".to_string()),
+                max_refresh: true,
+                auto_position: true,
+                mode_spec: Some(ModeSpec::MaxRefresh),
+            }),
+            reserved: Some((30, 0, 0, 0)),
+            extra_commands: vec!["keyword workspace 1,monitor:DP-1".to_string()],
+            create_if_missing: true,
+            primary: true,
+            mirror_group: Some("desk".to_string()),
+            mirror_source: true,
+        };
+        let saved = SavedConfig {
+            compositor: Some(Compositor {
+                name: "Hyprland".to_string(),
+                version: "0.45.2".to_string(),
+            }),
+            heads: vec![head],
+            hooks: vec!["notify-send {profile}".to_string()],
+            default_head: Some("Dell Inc. DELL U2720Q ABC123".to_string()),
+        };
+
+        let contents = serde_json::to_vec_pretty(&saved).unwrap();
+        assert_eq!(validate_profile(&contents), Vec::new());
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+use zbus::fdo;
+
+use crate::backend::Backend;
+use crate::config::read_config_file;
+use crate::events::{self, EventKind};
+use crate::info::Info;
+use crate::lock::lock_state_dir;
+use crate::profile::{
+    DEFAULT_PROFILE, apply_head_config, current_heads, heads_hash, load_head_config_with_fallbacks,
+    profile_path, run_hooks, save_head_config,
+};
+use crate::status::{LastApplied, Status};
+
+/// Well-known bus name of the wlscsr service
+pub const BUS_NAME: &str = "org.spacedentist.wlscsr";
+/// Object path the service is exported at
+pub const OBJECT_PATH: &str = "/org/spacedentist/wlscsr";
+/// Interface implemented by the service
+pub const INTERFACE: &str = "org.spacedentist.wlscsr";
+
+/// D-Bus service exposing save, restore and info on the session bus
+pub struct Service {
+    backend: Box<dyn Backend>,
+    started: Instant,
+    errors: AtomicU64,
+}
+
+/// Whether the service is alive and what it last did
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Health {
+    /// Seconds since the service was started
+    pub uptime: u64,
+    pub last_applied: Option<LastApplied>,
+    /// Time of the newest event in the event log, in seconds since the Unix
+    /// epoch
+    pub last_event: Option<u64>,
+    /// Number of requests that failed since the service was started
+    pub errors: u64,
+}
+
+impl Service {
+    pub fn new(backend: Box<dyn Backend>) -> Self {
+        Self {
+            backend,
+            started: Instant::now(),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    fn failed(&self, err: anyhow::Error) -> fdo::Error {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        into_fdo_error(err)
+    }
+
+    fn do_save(&self, profile: &str) -> anyhow::Result<()> {
+        let _lock = lock_state_dir()?;
+        let config = read_config_file()?;
+        let (heads, _) = current_heads(self.backend.as_ref(), &config)?;
+        save_head_config(self.backend.as_ref(), &heads, profile, &config, true, false)?;
+        Ok(())
+    }
+
+    fn do_restore(&self, requested_profile: &str) -> anyhow::Result<()> {
+        let _lock = lock_state_dir()?;
+        let config = read_config_file()?;
+        let (heads, ignored_heads) = current_heads(self.backend.as_ref(), &config)?;
+        let (profile, saved) =
+            load_head_config_with_fallbacks(&heads, &ignored_heads, requested_profile, &config)?;
+        apply_head_config(self.backend.as_ref(), &saved, &heads, &config)?;
+        LastApplied::record(&profile, heads_hash(&heads, &config));
+        run_hooks(&saved, &profile);
+        events::record(EventKind::Applied {
+            profile: profile.clone(),
+            fallback: profile != requested_profile,
+        });
+        Ok(())
+    }
+
+    fn do_status(&self, profile: &str) -> anyhow::Result<String> {
+        let config = read_config_file()?;
+        let mut status = Status::new(self.backend.as_ref(), &config, profile)?;
+        status.daemon = true;
+        Ok(serde_json::to_string(&status)?)
+    }
+
+    fn do_health(&self) -> anyhow::Result<String> {
+        let health = Health {
+            uptime: self.started.elapsed().as_secs(),
+            last_applied: LastApplied::read(),
+            last_event: events::read_events(None)?.last().map(|event| event.time),
+            errors: self.errors.load(Ordering::Relaxed),
+        };
+        Ok(serde_json::to_string(&health)?)
+    }
+
+    fn do_info(&self) -> anyhow::Result<String> {
+        let config = read_config_file()?;
+        let (heads, ignored_heads) = current_heads(self.backend.as_ref(), &config)?;
+        let path = profile_path(&heads, DEFAULT_PROFILE, &config)?;
+        let info = Info::new(&heads, &ignored_heads, path);
+        Ok(serde_json::to_string(&info)?)
+    }
+}
+
+#[zbus::interface(name = "org.spacedentist.wlscsr")]
+impl Service {
+    /// Save the current screen configuration under the given profile
+    fn save(&self, profile: &str) -> fdo::Result<()> {
+        info!(profile, "D-Bus: save");
+        self.do_save(profile).map_err(|err| self.failed(err))
+    }
+
+    /// Restore the given profile for the connected heads
+    fn restore(&self, profile: &str) -> fdo::Result<()> {
+        info!(profile, "D-Bus: restore");
+        self.do_restore(profile)
+            .inspect_err(|err| {
+                events::record(EventKind::Error {
+                    message: format!("{:#}", err),
+                })
+            })
+            .map_err(|err| self.failed(err))
+    }
+
+    /// Information on connected heads as JSON
+    fn info(&self) -> fdo::Result<String> {
+        debug!("D-Bus: info");
+        self.do_info().map_err(|err| self.failed(err))
+    }
+
+    /// Status of the given profile and the last applied profile as JSON
+    fn status(&self, profile: &str) -> fdo::Result<String> {
+        debug!(profile, "D-Bus: status");
+        self.do_status(profile).map_err(|err| self.failed(err))
+    }
+
+    /// Uptime, last applied profile, time of the last event and number of
+    /// failed requests as JSON
+    fn health(&self) -> fdo::Result<String> {
+        debug!("D-Bus: health");
+        self.do_health().map_err(|err| self.failed(err))
+    }
+}
+
+fn into_fdo_error(err: anyhow::Error) -> fdo::Error {
+    error!("{}", err);
+    fdo::Error::Failed(err.to_string())
+}
+
+/// Register the service on the session bus and serve requests until the
+/// process is terminated
+pub fn serve(backend: Box<dyn Backend>) -> anyhow::Result<()> {
+    let _connection = zbus::blocking::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Service::new(backend))?
+        .build()?;
+    info!(name = BUS_NAME, "Serving on the session bus");
+
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Ask a running service for its status, failing if none is running
+pub fn query_status(profile: &str) -> anyhow::Result<Status> {
+    let connection = zbus::blocking::Connection::session()?;
+    let reply = connection.call_method(
+        Some(BUS_NAME),
+        OBJECT_PATH,
+        Some(INTERFACE),
+        "Status",
+        &(profile,),
+    )?;
+    let json: String = reply.body().deserialize()?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Ask a running service for its health, failing if none is running
+pub fn query_health() -> anyhow::Result<Health> {
+    let connection = zbus::blocking::Connection::session()?;
+    let reply =
+        connection.call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE), "Health", &())?;
+    let json: String = reply.body().deserialize()?;
+    Ok(serde_json::from_str(&json)?)
+}
@@ -0,0 +1,79 @@
+use crate::format::Format;
+use crate::types::SavedConfig;
+
+/// Read saved heads in the given format from a file path, from stdin if the
+/// path is `-`, or, if wlscsr is built with the `http` feature, from an
+/// `http://` or `https://` URL
+///
+/// Profiles fetched from a URL are cached in the state directory, and the
+/// cached copy is used if fetching fails.
+pub fn read_profile_source(source: &str, format: Format) -> anyhow::Result<SavedConfig> {
+    let contents = if source == "-" {
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut contents)?;
+        contents
+    } else if is_url(source) {
+        read_url(source, format)?
+    } else {
+        std::fs::read(source)?
+    };
+
+    format.parse(&contents)
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+#[cfg(feature = "http")]
+fn read_url(url: &str, format: Format) -> anyhow::Result<Vec<u8>> {
+    use tracing::{debug, warn};
+
+    let cache_file = format!(
+        "remote/{}.{}",
+        hex::encode(sha256(url.as_bytes())),
+        format.extension()
+    );
+
+    debug!(url, "Fetching profile");
+    let fetched = ureq::get(url)
+        .call()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut response| Ok(response.body_mut().read_to_vec()?))
+        .and_then(|contents| {
+            // Make sure not to cache anything that cannot be applied later
+            format.parse(&contents)?;
+            Ok(contents)
+        });
+
+    match fetched {
+        Ok(contents) => {
+            let path = crate::dirs::place_state_file(&cache_file)?;
+            debug!(path = %path.display(), "Caching fetched profile");
+            std::fs::write(path, &contents)?;
+            Ok(contents)
+        }
+        Err(err) => match crate::dirs::state_file(&cache_file)? {
+            path if path.exists() => {
+                warn!(url, %err, "Failed to fetch profile, using cached copy");
+                Ok(std::fs::read(path)?)
+            }
+            _ => Err(err.context(format!("failed to fetch {}", url))),
+        },
+    }
+}
+
+#[cfg(not(feature = "http"))]
+fn read_url(url: &str, _format: Format) -> anyhow::Result<Vec<u8>> {
+    Err(anyhow::anyhow!(
+        "cannot fetch {}: wlscsr was built without the http feature",
+        url
+    ))
+}
+
+#[cfg(feature = "http")]
+fn sha256(data: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(data).into()
+}
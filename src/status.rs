@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::backend::Backend;
+use crate::config::ConfigFile;
+use crate::dirs;
+use crate::profile::{
+    config_matches, current_heads, load_head_config, profile_path, saved_profiles,
+};
+
+/// State of the connected heads relative to the saved profiles, as printed by
+/// `wlscsr status`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Status {
+    /// Whether this status was reported by a running `wlscsr serve`
+    pub daemon: bool,
+    /// The profile the status was requested for
+    pub profile: String,
+    pub path: PathBuf,
+    /// Whether a config for the profile has been saved
+    pub saved: bool,
+    /// Whether the live layout matches the saved config of the profile
+    pub matches: bool,
+    /// Profiles saved for the connected heads whose config matches the live
+    /// layout
+    pub active_profiles: Vec<String>,
+    /// Profile last restored, whether by the daemon, `watch` or `restore`
+    pub last_applied: Option<LastApplied>,
+}
+
+/// State file holding the profile last restored
+const LAST_APPLIED_FILE: &str = "last-applied.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LastApplied {
+    pub profile: String,
+    /// Hash of the heads the profile was restored for, as in the name of its
+    /// file
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+}
+
+impl LastApplied {
+    /// Remember that `profile` has been restored successfully for the heads
+    /// with the given hash. Failures are logged, but otherwise ignored.
+    pub fn record(profile: &str, hash: String) {
+        let result = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(anyhow::Error::from)
+            .and_then(|time| {
+                let last_applied = LastApplied {
+                    profile: profile.to_string(),
+                    hash: Some(hash),
+                    time: time.as_secs(),
+                };
+                debug!(?last_applied, "Recording last applied profile");
+                std::fs::write(
+                    dirs::place_state_file(LAST_APPLIED_FILE)?,
+                    serde_json::to_string_pretty(&last_applied)?,
+                )?;
+                Ok(())
+            });
+        if let Err(err) = result {
+            warn!(%err, "Failed to record last applied profile");
+        }
+    }
+
+    /// The profile last recorded with `record`, if any
+    pub fn read() -> Option<Self> {
+        let contents = std::fs::read(dirs::state_file(LAST_APPLIED_FILE).ok()?).ok()?;
+        serde_json::from_slice(&contents)
+            .map_err(|err| warn!(%err, "Ignoring invalid last applied profile"))
+            .ok()
+    }
+}
+
+impl Status {
+    pub fn new(backend: &dyn Backend, config: &ConfigFile, profile: &str) -> anyhow::Result<Self> {
+        let (heads, ignored_heads) = current_heads(backend, config)?;
+        let all_heads: Vec<_> = heads.iter().chain(ignored_heads.iter()).cloned().collect();
+
+        let is_active = |profile: &str| {
+            load_head_config(&heads, &ignored_heads, profile, config)
+                .map(|saved| config_matches(&saved.heads, &all_heads))
+                .unwrap_or(false)
+        };
+
+        let path = profile_path(&heads, profile, config)?;
+        Ok(Self {
+            daemon: false,
+            profile: profile.to_string(),
+            saved: path.exists(),
+            matches: is_active(profile),
+            path,
+            active_profiles: saved_profiles(&heads, config)?
+                .into_iter()
+                .filter(|p| is_active(p))
+                .collect(),
+            last_applied: LastApplied::read(),
+        })
+    }
+}
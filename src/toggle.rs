@@ -0,0 +1,96 @@
+use tracing::{debug, info};
+
+use crate::backend::Backend;
+use crate::config::{ConfigFile, HashAlgorithm};
+use crate::dirs;
+use crate::profile::{current_heads, hash_heads};
+use crate::types::{Head, HeadConfig, Transform};
+
+/// Find the connected head with the given name, ignored or not
+fn find_head(backend: &dyn Backend, name: &str, config: &ConfigFile) -> anyhow::Result<Head> {
+    let (heads, ignored_heads) = current_heads(backend, config)?;
+    heads
+        .into_iter()
+        .chain(ignored_heads)
+        .find(|h| h.name.as_deref() == Some(name))
+        .ok_or_else(|| anyhow::anyhow!("no head named {} is connected", name))
+}
+
+/// Name of the state file remembering the config of a disabled head, with
+/// the hash written with the given algorithm
+fn last_enabled_file_name(head: &Head, algorithm: HashAlgorithm, config: &ConfigFile) -> String {
+    format!(
+        "disabled/{}.json",
+        algorithm.encode(&hash_heads(std::slice::from_ref(head), config))
+    )
+}
+
+/// Turn off the head with the given name, remembering its config so that
+/// `enable_head` can restore it
+pub fn disable_head(backend: &dyn Backend, name: &str, config: &ConfigFile) -> anyhow::Result<()> {
+    let mut head = find_head(backend, name, config)?;
+    let Some(ref head_config) = head.config else {
+        info!(head = name, "Head is already disabled");
+        return Ok(());
+    };
+
+    let path = dirs::place_state_file(&last_enabled_file_name(&head, config.hash, config))?;
+    debug!(path = %path.display(), "Remembering config of head");
+    std::fs::write(&path, serde_json::to_string_pretty(head_config)?)?;
+
+    head.config = None;
+    backend.set_head_config(&[head])
+}
+
+/// Turn on the head with the given name, with the config it had when it was
+/// disabled with `disable_head`, or the preferred mode if that is not known
+pub fn enable_head(backend: &dyn Backend, name: &str, config: &ConfigFile) -> anyhow::Result<()> {
+    let mut head = find_head(backend, name, config)?;
+    if head.config.is_some() {
+        info!(head = name, "Head is already enabled");
+        return Ok(());
+    }
+
+    // The head may have been disabled before `hash` was changed
+    let mut path = dirs::state_file(&last_enabled_file_name(&head, config.hash, config))?;
+    if !path.exists() {
+        path = dirs::state_file(&last_enabled_file_name(
+            &head,
+            HashAlgorithm::Sha256,
+            config,
+        ))?;
+    }
+    let last_enabled = std::fs::read(&path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<HeadConfig>(&contents).ok());
+
+    match last_enabled {
+        Some(head_config) => {
+            debug!(path = %path.display(), "Restoring remembered config of head");
+            head.config = Some(head_config);
+            backend.set_head_config(&[head])
+        }
+        None => {
+            debug!(head = name, "No remembered config, using preferred mode");
+            backend.fallback_head_config(&[name.to_string()], &[])
+        }
+    }
+}
+
+/// Change the transform of the head with the given name, leaving everything
+/// else as it is
+pub fn rotate_head(
+    backend: &dyn Backend,
+    name: &str,
+    transform: Transform,
+    config: &ConfigFile,
+) -> anyhow::Result<()> {
+    let mut head = find_head(backend, name, config)?;
+    let Some(ref mut head_config) = head.config else {
+        return Err(anyhow::anyhow!("head {} is disabled", name));
+    };
+
+    debug!(head = name, from = %head_config.transform, to = %transform, "Rotating head");
+    head_config.transform = transform;
+    backend.set_head_config(&[head])
+}
@@ -10,28 +10,439 @@ pub struct Head {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub config: Option<HeadConfig>,
+    /// Area reserved at the top, bottom, left and right edges, for backends
+    /// that support it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub reserved: Option<(i32, i32, i32, i32)>,
+    /// Raw backend commands applied after configuring this head, for backends
+    /// that accept them (e.g. `keyword workspace 1,monitor:DP-1` for hyprctl)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub extra_commands: Vec<String>,
+    /// Create this head as a headless output when restoring, if it is not
+    /// connected, for backends that support it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub create_if_missing: bool,
+    /// Whether this is the primary head, i.e. the focused monitor in Hyprland,
+    /// for backends that have such a concept
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub primary: bool,
+    /// Heads with the same mirror group show the same picture: that of the
+    /// one head in the group with `mirror_source`, for backends that support
+    /// mirroring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub mirror_group: Option<String>,
+    /// Whether this head is mirrored by the other heads of its mirror group
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub mirror_source: bool,
+}
+
+/// Contents of a saved screen config file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SavedConfig {
+    /// The compositor the config was saved with, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub compositor: Option<Compositor>,
+    pub heads: Vec<Head>,
+    /// Shell commands run after the config has been applied
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub hooks: Vec<String>,
+    /// The head new windows and the cursor go to, as make, model and serial
+    /// separated by spaces (see `Head::is_wanted`), if the backend can tell.
+    /// Takes precedence over `primary` in the saved heads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub default_head: Option<String>,
+}
+
+/// Name and version of a compositor
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Compositor {
+    pub name: String,
+    pub version: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HeadConfig {
+    /// Width, height and refresh rate may be left out if `mode_spec` is given
+    #[serde(default)]
     pub width: i32,
+    #[serde(default)]
     pub height: i32,
+    #[serde(default)]
     pub refresh_rate: f64,
     pub x: i32,
     pub y: i32,
     pub scale: f64,
-    pub transform: i32,
+    pub transform: Transform,
     pub vrr: bool,
+    /// Set if the mode is not one of the modes reported by the head. For
+    /// hyprctl, this is used as the mode verbatim, so it may also be a
+    /// modeline. wlr-randr sets width, height and refresh rate as a custom
+    /// mode instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub custom_mode: Option<String>,
+    /// Bit depth (e.g. 10), for backends that support it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub bitdepth: Option<u8>,
+    /// Color management preset (e.g. `hdr` for Hyprland), for backends that
+    /// support it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub cm: Option<String>,
+    /// Brightness of SDR content in HDR mode, for backends that support it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub sdr_brightness: Option<f64>,
+    /// Saturation of SDR content in HDR mode, for backends that support it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub sdr_saturation: Option<f64>,
+    /// Whether tearing (immediate presentation) is allowed, for backends that
+    /// support it. Hyprland only has a global setting, which is turned on if
+    /// any head has `true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub tearing: Option<bool>,
+    /// Connector name of the head whose contents this head shows instead of
+    /// its own, for backends that support mirroring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub mirror_of: Option<String>,
+    /// Run at the highest refresh rate available for the saved resolution,
+    /// rather than the saved refresh rate
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub max_refresh: bool,
+    /// Let the compositor place the head to the right of the others instead
+    /// of at `x` and `y`, for backends that support it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default)]
+    pub auto_position: bool,
+    /// Mode picked from the modes available when restoring, replacing
+    /// width, height and refresh rate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, rename = "mode")]
+    pub mode_spec: Option<ModeSpec>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Rotation and reflection of a head, saved as the Wayland `wl_output`
+/// transform number (0 to 7)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "i32", into = "i32")]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HeadMode {
     pub width: i32,
     pub height: i32,
     pub refresh_rate: f64,
 }
 
+/// A mode given by label rather than resolution and refresh rate, saved as
+/// `native`, `max-refresh`, `preferred` or e.g. `3840x2160@120`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum ModeSpec {
+    Exact(HeadMode),
+    /// The highest resolution, at its highest refresh rate
+    Native,
+    /// The highest refresh rate, at the highest resolution offering it
+    MaxRefresh,
+    /// The mode the head reports as preferred
+    Preferred,
+}
+
+/// The head a workspace is shown on
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceAssignment {
+    /// Workspace as understood by the backend, e.g. `1` or `name:mail` for
+    /// hyprctl
+    pub workspace: String,
+    pub head: String,
+}
+
+/// A mode supported by a head
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AvailableMode {
+    #[serde(flatten)]
+    pub mode: HeadMode,
+    pub current: bool,
+    pub preferred: bool,
+}
+
+impl HeadMode {
+    /// Whether both modes have the same resolution and refresh rate, allowing
+    /// for rounding of the refresh rate
+    pub fn matches(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && (self.refresh_rate - other.refresh_rate).abs() < 0.01
+    }
+}
+
+impl HeadConfig {
+    pub fn mode(&self) -> HeadMode {
+        HeadMode {
+            width: self.width,
+            height: self.height,
+            refresh_rate: self.refresh_rate,
+        }
+    }
+
+    /// Size of the area the head covers in the layout, taking scale and
+    /// transform into account
+    pub fn logical_size(&self) -> (i32, i32) {
+        let (width, height) = match self.transform {
+            Transform::Rotate90
+            | Transform::Rotate270
+            | Transform::Flipped90
+            | Transform::Flipped270 => (self.height, self.width),
+            _ => (self.width, self.height),
+        };
+        (
+            (width as f64 / self.scale).round() as i32,
+            (height as f64 / self.scale).round() as i32,
+        )
+    }
+}
+
+impl Transform {
+    const ALL: [Transform; 8] = [
+        Transform::Normal,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+}
+
+impl TryFrom<i32> for Transform {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        usize::try_from(value)
+            .ok()
+            .and_then(|idx| Self::ALL.get(idx).copied())
+            .ok_or_else(|| format!("invalid transform {}, must be 0 to 7", value))
+    }
+}
+
+impl From<Transform> for i32 {
+    fn from(transform: Transform) -> Self {
+        transform as i32
+    }
+}
+
+/// Accepts the transform number (0 to 7), the names used by wlr-randr (e.g.
+/// `90` or `flipped-270`), and `left`, `right` and `inverted`
+impl std::str::FromStr for Transform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => return Ok(Transform::Rotate90),
+            "right" => return Ok(Transform::Rotate270),
+            "inverted" => return Ok(Transform::Rotate180),
+            _ => (),
+        }
+        if let Some(transform) = Self::ALL.into_iter().find(|t| t.to_string() == s) {
+            return Ok(transform);
+        }
+        s.parse::<i32>()
+            .map_err(|_| format!("invalid transform {:?}", s))
+            .and_then(Self::try_from)
+    }
+}
+
+/// The name used by wlr-randr
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Transform::Normal => "normal",
+            Transform::Rotate90 => "90",
+            Transform::Rotate180 => "180",
+            Transform::Rotate270 => "270",
+            Transform::Flipped => "flipped",
+            Transform::Flipped90 => "flipped-90",
+            Transform::Flipped180 => "flipped-180",
+            Transform::Flipped270 => "flipped-270",
+        })
+    }
+}
+
+impl std::str::FromStr for ModeSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(ModeSpec::Native),
+            "max-refresh" => Ok(ModeSpec::MaxRefresh),
+            "preferred" => Ok(ModeSpec::Preferred),
+            _ => {
+                let invalid = || {
+                    format!(
+                        "invalid mode {:?}, must be native, max-refresh, preferred or \
+                         like 3840x2160@60",
+                        s
+                    )
+                };
+                let (size, refresh_rate) = s.split_once('@').ok_or_else(invalid)?;
+                let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+                Ok(ModeSpec::Exact(HeadMode {
+                    width: width.parse().map_err(|_| invalid())?,
+                    height: height.parse().map_err(|_| invalid())?,
+                    refresh_rate: refresh_rate
+                        .trim_end_matches("Hz")
+                        .parse()
+                        .map_err(|_| invalid())?,
+                }))
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for ModeSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ModeSpec> for String {
+    fn from(spec: ModeSpec) -> Self {
+        match spec {
+            ModeSpec::Exact(mode) => {
+                format!("{}x{}@{}", mode.width, mode.height, mode.refresh_rate)
+            }
+            ModeSpec::Native => "native".to_string(),
+            ModeSpec::MaxRefresh => "max-refresh".to_string(),
+            ModeSpec::Preferred => "preferred".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for HeadMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}@{}Hz", self.width, self.height, self.refresh_rate)
+    }
+}
+
+impl SavedConfig {
+    /// Parse a saved screen config. Older versions saved just the list of
+    /// heads, which is accepted as well.
+    pub fn from_slice(contents: &[u8]) -> serde_json::Result<Self> {
+        if contents.trim_ascii_start().starts_with(b"[") {
+            Ok(Self {
+                heads: serde_json::from_slice(contents)?,
+                ..Default::default()
+            })
+        } else {
+            serde_json::from_slice(contents)
+        }
+    }
+}
+
+impl std::fmt::Display for Compositor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+/// What to replace with placeholders when heads are exported to be shared
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anonymize {
+    /// Serial numbers only
+    Serials,
+    /// Make and model as well as serial numbers
+    All,
+}
+
+impl std::str::FromStr for Anonymize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "serials" => Ok(Anonymize::Serials),
+            "all" => Ok(Anonymize::All),
+            _ => Err(format!("invalid value {:?}, must be serials or all", s)),
+        }
+    }
+}
+
 impl Head {
+    /// Replace the serial (and make and model, if so asked) with a placeholder
+    /// derived from its hash, so that equal values still match each other.
+    /// Empty values are kept, since they mean something else.
+    pub fn anonymize(&mut self, anonymize: Anonymize) {
+        use sha2::{Digest, Sha256};
+
+        let placeholder = |prefix: &str, s: &mut String| {
+            if !s.is_empty() {
+                let hash = hex::encode(Sha256::digest(s.as_bytes()));
+                *s = format!("{}-{}", prefix, &hash[..8]);
+            }
+        };
+        placeholder("serial", &mut self.serial);
+        if anonymize == Anonymize::All {
+            placeholder("make", &mut self.make);
+            placeholder("model", &mut self.model);
+        }
+    }
+
+    /// Whether the head is the one given by `wanted`, which is either a
+    /// connector name, a serial number or make, model and serial separated by
+    /// spaces
+    pub fn is_wanted(&self, wanted: &str) -> bool {
+        self.name.as_deref() == Some(wanted)
+            || (!self.serial.is_empty() && self.serial == wanted)
+            || format!("{} {} {}", self.make, self.model, self.serial) == wanted
+    }
+
+    /// Make, model and serial separated by spaces, as accepted by
+    /// `is_wanted`, unless all of them are empty
+    pub fn identity_reference(&self) -> Option<String> {
+        (!self.make.is_empty() || !self.model.is_empty() || !self.serial.is_empty())
+            .then(|| format!("{} {} {}", self.make, self.model, self.serial))
+    }
+
+    /// Whether `query` is the connector name of the head, or part of its
+    /// make, model or serial
+    pub fn matches_query(&self, query: &str) -> bool {
+        self.name.as_deref() == Some(query)
+            || [&self.make, &self.model, &self.serial]
+                .iter()
+                .any(|s| s.contains(query))
+    }
+
+    /// Whether both heads have the same make, model and serial
+    pub fn same_mms(&self, other: &Self) -> bool {
+        (&self.make, &self.model, &self.serial) == (&other.make, &other.model, &other.serial)
+    }
+
     pub fn cmp_mms(&self, other: &Self) -> std::cmp::Ordering {
         self.make
             .cmp(&other.make)
@@ -39,3 +450,78 @@ impl Head {
             .then_with(|| self.serial.cmp(&other.serial))
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A 2256x1504 head at 0,0, for other tests to adjust
+    pub(crate) fn head_config(transform: Transform, scale: f64) -> HeadConfig {
+        HeadConfig {
+            width: 2256,
+            height: 1504,
+            refresh_rate: 60.0,
+            x: 0,
+            y: 0,
+            scale,
+            transform,
+            vrr: false,
+            custom_mode: None,
+            bitdepth: None,
+            cm: None,
+            sdr_brightness: None,
+            sdr_saturation: None,
+            tearing: None,
+            mirror_of: None,
+            max_refresh: false,
+            auto_position: false,
+            mode_spec: None,
+        }
+    }
+
+    /// A head connected to `name`, made by Dell
+    pub(crate) fn head(name: &str, serial: &str, config: Option<HeadConfig>) -> Head {
+        Head {
+            name: Some(name.to_string()),
+            make: "Dell Inc.".to_string(),
+            model: "DELL U2720Q".to_string(),
+            serial: serial.to_string(),
+            config,
+            reserved: None,
+            extra_commands: Vec::new(),
+            create_if_missing: false,
+            primary: false,
+            mirror_group: None,
+            mirror_source: false,
+        }
+    }
+
+    #[test]
+    fn logical_size() {
+        let expected = [
+            (Transform::Normal, (2256, 1504), (1504, 1003)),
+            (Transform::Rotate90, (1504, 2256), (1003, 1504)),
+            (Transform::Rotate180, (2256, 1504), (1504, 1003)),
+            (Transform::Rotate270, (1504, 2256), (1003, 1504)),
+            (Transform::Flipped, (2256, 1504), (1504, 1003)),
+            (Transform::Flipped90, (1504, 2256), (1003, 1504)),
+            (Transform::Flipped180, (2256, 1504), (1504, 1003)),
+            (Transform::Flipped270, (1504, 2256), (1003, 1504)),
+        ];
+        assert_eq!(expected.len(), Transform::ALL.len());
+        for (transform, unscaled, scaled) in expected {
+            assert_eq!(
+                head_config(transform, 1.0).logical_size(),
+                unscaled,
+                "{}",
+                transform
+            );
+            assert_eq!(
+                head_config(transform, 1.5).logical_size(),
+                scaled,
+                "{}",
+                transform
+            );
+        }
+    }
+}
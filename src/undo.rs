@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::backend::Backend;
+use crate::dirs;
+use crate::types::Head;
+
+/// State file holding the layouts to go back to, oldest first
+const UNDO_FILE: &str = "undo.json";
+/// Number of layouts kept in the undo file
+const MAX_UNDO_STEPS: usize = 10;
+
+/// A head as remembered in the undo file. Unlike in saved configs, the
+/// connector name is included, since that is what the layout is applied by.
+#[derive(Serialize, Deserialize)]
+struct UndoHead {
+    name: String,
+    #[serde(flatten)]
+    head: Head,
+}
+
+fn read_undo_stack() -> anyhow::Result<Vec<Vec<UndoHead>>> {
+    match std::fs::read(dirs::state_file(UNDO_FILE)?) {
+        Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write_undo_stack(stack: &[Vec<UndoHead>]) -> anyhow::Result<()> {
+    std::fs::write(
+        dirs::place_state_file(UNDO_FILE)?,
+        serde_json::to_string_pretty(stack)?,
+    )?;
+    Ok(())
+}
+
+/// Make a change to the layout with `change`, remembering the layout from
+/// before so that `undo` can go back to it, but only if the change succeeds.
+/// Failures to remember the layout are logged, but otherwise ignored, so as
+/// not to keep the layout from being changed.
+pub fn undoable<T>(
+    backend: &dyn Backend,
+    change: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let before = backend.get_all_heads();
+    let result = change()?;
+    if let Err(err) = before.and_then(remember_layout) {
+        warn!(%err, "Failed to remember layout for undo");
+    }
+    Ok(result)
+}
+
+/// Push `heads` onto the undo stack
+fn remember_layout(heads: Vec<Head>) -> anyhow::Result<()> {
+    let mut stack = read_undo_stack().unwrap_or_else(|err| {
+        warn!(%err, "Discarding invalid undo file");
+        Vec::new()
+    });
+    stack.push(
+        heads
+            .into_iter()
+            .filter_map(|head| {
+                Some(UndoHead {
+                    name: head.name.clone()?,
+                    head,
+                })
+            })
+            .collect(),
+    );
+    let excess = stack.len().saturating_sub(MAX_UNDO_STEPS);
+    stack.drain(..excess);
+    debug!(steps = stack.len(), "Remembering layout for undo");
+    write_undo_stack(&stack)
+}
+
+/// Go back to the layout before the last `steps` changes made with
+/// `undoable`
+pub fn undo(backend: &dyn Backend, steps: usize) -> anyhow::Result<()> {
+    let mut stack = read_undo_stack()?;
+    if steps == 0 || steps > stack.len() {
+        return Err(anyhow::anyhow!(
+            "cannot undo {} steps, only {} are remembered",
+            steps,
+            stack.len()
+        ));
+    }
+
+    let layout = stack
+        .drain(stack.len() - steps..)
+        .next()
+        .unwrap_or_default();
+    let heads: Vec<Head> = layout
+        .into_iter()
+        .map(|UndoHead { name, mut head }| {
+            head.name = Some(name);
+            head
+        })
+        .collect();
+    backend.set_head_config(&heads)?;
+    write_undo_stack(&stack)
+}
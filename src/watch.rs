@@ -0,0 +1,180 @@
+use std::time::{Duration, Instant};
+
+use tracing::{debug, error, info, warn};
+
+use crate::backend::Backend;
+use crate::config::read_config_file;
+use crate::events::{self, EventKind};
+use crate::lock::lock_state_dir;
+use crate::profile::{
+    apply_head_config, current_heads, heads_hash, load_head_config_with_fallbacks, run_hooks,
+};
+use crate::status::LastApplied;
+use crate::types::Head;
+
+pub struct WatchOptions {
+    /// Profile to restore when the connected heads change
+    pub profile: String,
+    /// How often to query the backend
+    pub interval: Duration,
+    /// How long the connected heads must stay the same before restoring
+    pub settle: Duration,
+    /// Exit after restoring once
+    pub once: bool,
+    /// Only log which profile would be restored, leaving it to the backend to
+    /// log the commands it would run instead of running them. Neither hooks
+    /// nor events are recorded.
+    pub dry_run: bool,
+}
+
+/// Which heads are connected, and which of them are ignored
+#[derive(Debug, PartialEq)]
+struct HeadSet(Vec<(Option<String>, String, String, String, bool)>);
+
+impl HeadSet {
+    fn new(heads: &[Head], ignored_heads: &[Head]) -> Self {
+        Self(
+            heads
+                .iter()
+                .map(|h| (h, false))
+                .chain(ignored_heads.iter().map(|h| (h, true)))
+                .map(|(h, ignored)| {
+                    (
+                        h.name.clone(),
+                        h.make.clone(),
+                        h.model.clone(),
+                        h.serial.clone(),
+                        ignored,
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Poll the backend until the head given by `wanted` (see `Head::is_wanted`) is
+/// connected, failing after `timeout`
+pub fn wait_for_head(
+    backend: &dyn Backend,
+    wanted: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    loop {
+        match backend.get_all_heads() {
+            Ok(heads) if heads.iter().any(|h| h.is_wanted(wanted)) => {
+                info!(head = wanted, "Head is connected");
+                return Ok(());
+            }
+            Ok(_) => debug!(head = wanted, "Waiting for head"),
+            Err(err) => warn!(%err, "Failed to query heads"),
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(anyhow::anyhow!(
+                "head {} was not connected within {}s",
+                wanted,
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Poll the backend and restore the saved config whenever the set of connected
+/// heads changes and then stays the same for `settle`
+///
+/// The config file is read again on every poll. If it becomes invalid, the
+/// last valid one is used until it is fixed.
+pub fn watch(backend: &dyn Backend, options: &WatchOptions) -> anyhow::Result<()> {
+    let mut applied: Option<HeadSet> = None;
+    let mut candidate: Option<(HeadSet, Instant)> = None;
+    let mut config = read_config_file()?;
+    let mut config_error: Option<String> = None;
+
+    loop {
+        match read_config_file() {
+            Ok(new_config) => {
+                if config_error.take().is_some() {
+                    info!("Config file is valid again");
+                }
+                config = new_config;
+            }
+            Err(err) => {
+                let message = format!("{:#}", err);
+                if config_error.as_ref() != Some(&message) {
+                    error!(
+                        "Invalid config file, keeping the last valid one: {}",
+                        message
+                    );
+                    config_error = Some(message);
+                }
+            }
+        }
+        match current_heads(backend, &config) {
+            Ok((heads, ignored_heads)) => {
+                let head_set = HeadSet::new(&heads, &ignored_heads);
+                match candidate {
+                    Some((ref candidate_set, since)) if *candidate_set == head_set => {
+                        if since.elapsed() >= options.settle && applied.as_ref() != Some(&head_set)
+                        {
+                            info!(heads = heads.len(), "Connected heads settled, restoring");
+                            let result = load_head_config_with_fallbacks(
+                                &heads,
+                                &ignored_heads,
+                                &options.profile,
+                                &config,
+                            )
+                            .and_then(|(profile, saved)| {
+                                if options.dry_run {
+                                    info!(profile, "Would restore profile");
+                                    return apply_head_config(backend, &saved, &heads, &config);
+                                }
+                                let _lock = lock_state_dir()?;
+                                apply_head_config(backend, &saved, &heads, &config)?;
+                                LastApplied::record(&profile, heads_hash(&heads, &config));
+                                run_hooks(&saved, &profile);
+                                events::record(EventKind::Applied {
+                                    fallback: profile != options.profile,
+                                    profile,
+                                });
+                                Ok(())
+                            });
+                            if let Err(ref err) = result
+                                && !options.dry_run
+                            {
+                                events::record(EventKind::Error {
+                                    message: format!("{:#}", err),
+                                });
+                            }
+
+                            if options.once {
+                                return result;
+                            }
+                            if let Err(err) = result {
+                                error!("{}", err);
+                            }
+                            applied = Some(head_set);
+                        }
+                    }
+                    _ => {
+                        debug!(?head_set, "Connected heads changed");
+                        if !options.dry_run {
+                            events::record(EventKind::HeadsChanged {
+                                heads: head_set.0.len(),
+                            });
+                        }
+                        candidate = Some((head_set, Instant::now()));
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(%err, "Failed to query heads");
+                candidate = None;
+            }
+        }
+
+        std::thread::sleep(options.interval);
+    }
+}